@@ -0,0 +1,66 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// Caps how many restarts may happen within a sliding window. Once the budget is spent
+// the breaker is open and restarts are refused until old ones age out or it is reset.
+#[derive(Clone)]
+pub struct CircuitBreaker {
+    max_restarts: u32,
+    window: Duration,
+    restarts: VecDeque<Instant>,
+}
+
+impl CircuitBreaker {
+    pub fn new(max_restarts: u32, window: Duration) -> Self {
+        Self {
+            max_restarts,
+            window,
+            restarts: VecDeque::new(),
+        }
+    }
+
+    pub fn set_limits(&mut self, max_restarts: u32, window: Duration) {
+        self.max_restarts = max_restarts;
+        self.window = window;
+    }
+
+    pub fn remaining(&mut self) -> u32 {
+        self.max_restarts.saturating_sub(self.recent())
+    }
+
+    // Restarts within the window
+    pub fn recent(&mut self) -> u32 {
+        while self.restarts.front().is_some_and(|at| at.elapsed() >= self.window) {
+            self.restarts.pop_front();
+        }
+        self.restarts.len() as u32
+    }
+
+    pub fn is_open(&mut self) -> bool {
+        self.remaining() == 0
+    }
+
+    pub fn record_restart(&mut self) {
+        self.restarts.push_back(Instant::now());
+    }
+
+    pub fn reset(&mut self) {
+        self.restarts.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn restarts_age_out_of_a_copy() {
+        let mut breaker = CircuitBreaker::new(1, Duration::from_millis(50));
+        breaker.record_restart();
+        let mut copy = breaker.clone();
+        assert!(copy.is_open());
+        thread::sleep(Duration::from_millis(60));
+        assert_eq!(copy.remaining(), 1);
+    }
+}
@@ -0,0 +1,127 @@
+use clap::{Args, Parser, Subcommand};
+use log::LevelFilter;
+use std::path::PathBuf;
+
+use crate::wizard::InitOptions;
+
+const AFTER_HELP: &str = "\
+The config file is taken from --config, then the RELINK_CONFIG environment variable,
+then the first config.json/.toml/.yaml next to the executable or in %ProgramData%\\Relink.
+Files in a config.d directory next to it are merged on top in lexical order.
+Any field can be overridden with a RELINK_<FIELD> variable (nested: RELINK_<SECTION>__<FIELD>)
+or --set <field>=<value>. Precedence: defaults < file < environment < --set.
+
+Without a command, Relink runs as a service and must be started by the SCM.";
+
+#[derive(Parser, Debug)]
+#[command(name = "relink", version, about = "Relink Network Monitor Service", after_help = AFTER_HELP)]
+pub struct Cli {
+    #[command(flatten)]
+    pub global: GlobalArgs,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Args, Debug)]
+pub struct GlobalArgs {
+    /// Config file to use instead of the search path
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Override a config field, applied on top of the file and environment
+    #[arg(long = "set", global = true, value_name = "FIELD=VALUE", value_parser = parse_override)]
+    pub overrides: Vec<(String, String)>,
+
+    /// Log level for the console and the log file
+    #[arg(long, global = true, value_name = "LEVEL", default_value = "info")]
+    pub log_level: LevelFilter,
+
+    /// Only print errors to the console, the log file is unaffected
+    #[arg(long, short, global = true)]
+    pub quiet: bool,
+
+    /// Print machine-readable JSON on stdout where supported, logs go to stderr
+    #[arg(long, global = true)]
+    pub json: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Install and start the service (requires admin)
+    Install,
+    /// Stop and uninstall the service (requires admin)
+    Uninstall,
+    /// Monitor in the foreground without the SCM, Ctrl-C to stop
+    Run,
+    /// Check the adapter once
+    #[command(after_help = "Exit codes: 0 healthy, 1 error, 3 fixed, 4 degraded but not fixed, 5 adapter not found")]
+    Check {
+        /// Treat the check as a wake event, so a degraded link is fixed
+        #[arg(long)]
+        force: bool,
+        /// Only detect, never restart the adapter
+        #[arg(long)]
+        no_fix: bool,
+    },
+    /// Restart the adapter and verify the link comes back (requires admin)
+    #[command(after_help = "Exit codes: 0 recovered, 1 error, 4 still degraded, 5 not found, 6 service busy, 7 vetoed by the pre-restart hook")]
+    Restart {
+        /// Adapter name or part of it, defaults to target_adapter_name
+        adapter: Option<String>,
+    },
+    /// List the network adapters and mark the one the config selects
+    ListAdapters,
+    /// Show what the running service is doing
+    #[command(after_help = "Exit codes: 0 running, 1 error, 3 service not running")]
+    Status,
+    /// Show the most recent adapter restarts
+    History {
+        /// Number of restarts to show
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Send a command to the running service (requires admin)
+    Control {
+        #[arg(value_parser = ["check", "reload", "reset-breaker", "pause", "resume"])]
+        action: String,
+    },
+    /// Inspect, create and convert config files
+    #[command(subcommand)]
+    Config(ConfigCommand),
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Pick the adapter to monitor from the detected ones and write a config
+    Init(InitOptions),
+    /// Check a config file (default: the merged config) and exit non-zero on problems
+    Validate {
+        path: Option<PathBuf>,
+    },
+    /// Upgrade a config file to the current version, keeping a .bak copy
+    Migrate {
+        path: Option<PathBuf>,
+        /// Print the migrated config without writing it
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Print the config file, or the merged config and where each value came from
+    Show {
+        #[arg(long)]
+        effective: bool,
+    },
+    /// Print the JSON Schema for the config file
+    Schema,
+    /// Convert between config.json, config.toml and config.yaml
+    Convert {
+        input: PathBuf,
+        output: PathBuf,
+    },
+}
+
+fn parse_override(raw: &str) -> Result<(String, String), String> {
+    raw.split_once('=')
+        .map(|(field, value)| (field.to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected <field>=<value>, got '{}'", raw))
+}
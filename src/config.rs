@@ -1,68 +1,729 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::fs::File;
-use std::path::PathBuf;
-use std::io::Write;
+use std::fmt;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::io::{self, Write};
+use std::net::SocketAddr;
 use std::env;
-use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock, RwLock};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::format::ConfigFormat;
+use crate::hooks::HooksConfig;
+use crate::journald::JournaldConfig;
+use crate::layers::{env_overrides, Layers, Provenance, Source};
+use crate::migration::{self, CURRENT_CONFIG_VERSION};
+use crate::mqtt::MqttConfig;
+use crate::syslog::SyslogConfig;
+use crate::units::{Interval, LinkSpeed};
+use crate::webhook::WebhookConfig;
 
 pub const DEFAULT_CONFIG_FILENAME: &str = "config.json";
+pub const CONFIG_PATH_ENV: &str = "RELINK_CONFIG";
+// Drop-in directory next to the main config file, merged in lexical order
+pub const DROP_IN_DIR: &str = "config.d";
+const CONFIG_FILE_STEM: &str = "config";
+
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+// Readers take an Arc snapshot, so a reload never changes the config under a running check.
+static CONFIG: RwLock<Option<Arc<AppConfig>>> = RwLock::new(None);
+
+// Set from --config, takes precedence over RELINK_CONFIG and the search path
+static PATH_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+// Set from --set <field>=<value>, applied on top of the file and environment
+static CLI_OVERRIDES: OnceLock<Vec<(String, String)>> = OnceLock::new();
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    UnsupportedFormat(PathBuf),
+    Parse {
+        path: PathBuf,
+        position: Option<(usize, usize)>,
+        message: String,
+    },
+    Invalid(PathBuf, Vec<String>),
+    Override(Source, String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "Failed to access {}: {}", path.display(), e),
+            ConfigError::UnsupportedFormat(path) => {
+                write!(f, "Unsupported config format {}, expected .json, .toml, .yaml or .yml", path.display())
+            }
+            ConfigError::Parse { path, position: Some((line, column)), message } => {
+                write!(f, "{}:{}:{}: {}", path.display(), line, column, message)
+            }
+            ConfigError::Parse { path, position: None, message } => {
+                write!(f, "{}: {}", path.display(), message)
+            }
+            ConfigError::Invalid(path, problems) => {
+                write!(f, "Invalid config {}:", path.display())?;
+                for problem in problems {
+                    write!(f, "\n  - {}", problem)?;
+                }
+                Ok(())
+            }
+            ConfigError::Override(source, message) => write!(f, "Invalid override from {}: {}", source, message),
+        }
+    }
+}
 
-pub static CONFIG: OnceLock<AppConfig> = OnceLock::new();
+impl std::error::Error for ConfigError {}
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+// The doc comments below become the descriptions in `relink config schema`.
+/// Relink network monitor configuration
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
 pub struct AppConfig {
+    /// Editor hint pointing at the JSON Schema, ignored by Relink
+    #[serde(rename = "$schema", skip_serializing_if = "Option::is_none")]
+    pub schema: Option<String>,
+    /// Config layout version, upgraded with `relink config migrate`
+    #[schemars(range(min = 1, max = CURRENT_CONFIG_VERSION))]
+    pub version: u32,
+    /// Windows service name used by install, uninstall and control
+    #[schemars(length(min = 1))]
     pub service_name: String,
+    /// Name shown in the Services console
+    #[schemars(length(min = 1))]
     pub service_display_name: String,
+    /// Part of the adapter's friendly name or description to monitor
+    #[schemars(length(min = 1))]
     pub target_adapter_name: String,
-    pub link_speed_threshold_bps: u64,
-    pub wait_after_wake_secs: u64,
-    pub restart_delay_secs: u64,
+    /// Link speeds at or below this count as degraded (e.g. "100Mbps")
+    pub link_speed_threshold_bps: LinkSpeed,
+    /// How long to let the adapter settle after a wake before checking it (e.g. "15s")
+    pub wait_after_wake_secs: Interval,
+    /// Pause between disabling and re-enabling the adapter (e.g. "3s")
+    pub restart_delay_secs: Interval,
+    /// Restarts allowed within breaker_window_secs before the circuit breaker opens
+    #[schemars(range(min = 1))]
+    pub breaker_max_restarts: u32,
+    /// Sliding window for breaker_max_restarts (e.g. "1h")
+    pub breaker_window_secs: Interval,
+    /// Prometheus metrics endpoint
+    pub metrics: MetricsConfig,
+    /// Endpoints notified on degradation, restarts, failed recoveries, vetoed restarts and the circuit breaker opening
+    pub webhooks: Vec<WebhookConfig>,
+    /// MQTT broker for state, events and commands, with Home Assistant discovery
+    pub mqtt: MqttConfig,
+    /// Commands run before and after adapter restarts
+    pub hooks: HooksConfig,
+    /// RFC 5424 syslog output
+    pub syslog: SyslogConfig,
+    /// systemd journal output, Linux only
+    pub journald: JournaldConfig,
+}
+
+/// Prometheus metrics endpoint, served by the running service
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    /// Serve /metrics over HTTP
+    pub enabled: bool,
+    /// Loopback address and port to listen on (e.g. "127.0.0.1:9185")
+    pub listen: String,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: "127.0.0.1:9185".to_string(),
+        }
+    }
+}
+
+impl MetricsConfig {
+    pub fn address(&self) -> Option<SocketAddr> {
+        self.listen.parse().ok()
+    }
 }
 
 impl Default for AppConfig {
     fn default() -> Self {
         Self {
+            schema: None,
+            version: CURRENT_CONFIG_VERSION,
             service_name: "RelinkNetworkService".to_string(),
             service_display_name: "Relink Network Monitor Service".to_string(),
             target_adapter_name: "Realtek Gaming USB 2.5GbE Family Controller".to_string(),
-            link_speed_threshold_bps: 100_000_000,
-            wait_after_wake_secs: 15,
-            restart_delay_secs: 3,
+            link_speed_threshold_bps: LinkSpeed(100_000_000),
+            wait_after_wake_secs: Interval::from_secs(15),
+            restart_delay_secs: Interval::from_secs(3),
+            breaker_max_restarts: 3,
+            breaker_window_secs: Interval::from_secs(3600),
+            metrics: MetricsConfig::default(),
+            webhooks: Vec::new(),
+            mqtt: MqttConfig::default(),
+            hooks: HooksConfig::default(),
+            syslog: SyslogConfig::default(),
+            journald: JournaldConfig::default(),
         }
     }
 }
 
 impl AppConfig {
+    pub fn set_path_override(path: PathBuf) {
+        let _ = PATH_OVERRIDE.set(std::path::absolute(&path).unwrap_or(path));
+    }
+
+    pub fn set_cli_overrides(overrides: Vec<(String, String)>) {
+        let _ = CLI_OVERRIDES.set(overrides);
+    }
+
+    // --config, then RELINK_CONFIG, then the first config.{json,toml,yaml,yml} found in
+    // the search directories. Falls back to config.json in the default directory.
     pub fn get_path() -> PathBuf {
-        let mut path = env::current_exe().unwrap_or_default();
-        path.set_file_name(DEFAULT_CONFIG_FILENAME);
-        path
+        if let Some(path) = PATH_OVERRIDE.get() {
+            return path.clone();
+        }
+        if let Some(path) = env::var_os(CONFIG_PATH_ENV).filter(|path| !path.is_empty()) {
+            let path = PathBuf::from(path);
+            return std::path::absolute(&path).unwrap_or(path);
+        }
+
+        search_dirs()
+            .iter()
+            .flat_map(|dir| {
+                ConfigFormat::ALL
+                    .iter()
+                    .map(move |(_, extension)| dir.join(format!("{}.{}", CONFIG_FILE_STEM, extension)))
+            })
+            .find(|path| path.exists())
+            .unwrap_or_else(|| default_config_dir().join(DEFAULT_CONFIG_FILENAME))
+    }
+
+    pub fn load() -> Result<Self, ConfigError> {
+        Self::load_effective().map(|(config, _)| config)
     }
 
-    pub fn load() -> Self {
+    // Layers defaults, the config file, config.d/* drop-ins, RELINK_* variables and --set
+    // flags, later ones winning. Defaults are only used as a whole when there is no config at all.
+    pub fn load_effective() -> Result<(Self, Provenance), ConfigError> {
+        let defaults = serde_json::to_value(Self::default()).expect("default config serializes");
+        let mut layers = Layers::new(defaults);
+
         let path = Self::get_path();
-        if let Ok(file) = File::open(&path) {
-            if let Ok(config) = serde_json::from_reader(file) {
-                return config;
+        if path.exists() {
+            let (value, _) = Self::read_file(&path)?;
+            layers.merge(value, &Source::File(path.clone()));
+        }
+
+        for drop_in in drop_in_files(&path)? {
+            let value = Self::read_drop_in(&drop_in)?;
+            layers.merge(value, &Source::File(drop_in.clone()));
+            Self::check_layers(&layers)
+                .map_err(|e| ConfigError::Invalid(drop_in.clone(), vec![e]))?;
+        }
+
+        let schema = Self::json_schema();
+        for (name, field, value) in env_overrides(env::vars()) {
+            // Other tools may use the prefix too, so unknown names don't fail the load
+            if !has_field(&schema, &field) {
+                log::warn!("Ignoring {}, it doesn't name a config field.", name);
+                continue;
             }
+            Self::apply_override(&mut layers, &field, &value, Source::Env(name))?;
         }
-        Self::default()
+        for (key, value) in CLI_OVERRIDES.get().into_iter().flatten() {
+            let field: Vec<String> = key.split('.').map(str::to_string).collect();
+            Self::apply_override(&mut layers, &field, value, Source::Cli(key.clone()))?;
+        }
+
+        let (value, provenance) = layers.into_parts();
+        let config: Self = serde_json::from_value(value)
+            .map_err(|e| ConfigError::Invalid(path.clone(), vec![e.to_string()]))?;
+
+        // Name the file, variable or flag that set each offending value
+        let problems: Vec<String> = config
+            .problems()
+            .into_iter()
+            .map(|(field, problem)| match provenance.get(field) {
+                Some(source @ (Source::File(_) | Source::Env(_) | Source::Cli(_))) => {
+                    format!("{} (set by {})", problem, source)
+                }
+                _ => problem,
+            })
+            .collect();
+        if !problems.is_empty() {
+            return Err(ConfigError::Invalid(path, problems));
+        }
+        Ok((config, provenance))
     }
 
-    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let path = Self::get_path();
-        let json = serde_json::to_string_pretty(self)?;
-        let mut file = File::create(path)?;
-        file.write_all(json.as_bytes())?;
+    fn check_layers(layers: &Layers) -> Result<(), String> {
+        serde_json::from_value::<Self>(layers.value().clone())
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    // Checks each override on its own so a bad value is blamed on the variable or flag that set it
+    fn apply_override(layers: &mut Layers, field: &[String], value: &str, source: Source) -> Result<(), ConfigError> {
+        layers
+            .set(field, value, source.clone())
+            .map_err(|e| ConfigError::Override(source.clone(), e))?;
+        let Err(error) = Self::check_layers(layers) else {
+            return Ok(());
+        };
+        // Optional text fields such as mqtt.password default to null, so "123456" was read
+        // as a number. Try it as text before giving up, blaming the first reading.
+        layers
+            .set_text(field, value, source.clone())
+            .map_err(|e| ConfigError::Override(source.clone(), e))?;
+        Self::check_layers(layers).map_err(|_| ConfigError::Override(source, error))
+    }
+
+    // A single file on its own, without environment or CLI overrides
+    pub fn load_from(path: &Path) -> Result<Self, ConfigError> {
+        let (value, _) = Self::read_file(path)?;
+        Self::from_value(path, value)
+    }
+
+    fn from_value(path: &Path, value: serde_json::Value) -> Result<Self, ConfigError> {
+        let config: Self = serde_json::from_value(value)
+            .map_err(|e| ConfigError::Invalid(path.to_path_buf(), vec![e.to_string()]))?;
+
+        let problems = config.validate();
+        if !problems.is_empty() {
+            return Err(ConfigError::Invalid(path.to_path_buf(), problems));
+        }
+        Ok(config)
+    }
+
+    // Reads a file into a value upgraded to the current version. Also returns the version
+    // the file was written in.
+    fn read_file(path: &Path) -> Result<(serde_json::Value, u32), ConfigError> {
+        let content = fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+        let format = ConfigFormat::from_path(path).ok_or_else(|| ConfigError::UnsupportedFormat(path.to_path_buf()))?;
+        let mut value: serde_json::Value = format.deserialize(path, &content)?;
+        let from_version = migration::migrate(&mut value)
+            .map_err(|e| ConfigError::Invalid(path.to_path_buf(), vec![e]))?;
+
+        if from_version == CURRENT_CONFIG_VERSION {
+            // Parse the original text as well so type errors keep their position
+            format.deserialize::<Self>(path, &content)?;
+        } else {
+            log::warn!(
+                "{} uses config version {}, upgraded in memory to {}. Run 'relink config migrate' to update the file.",
+                path.display(), from_version, CURRENT_CONFIG_VERSION
+            );
+        }
+        Ok((value, from_version))
+    }
+
+    // Drop-ins hold only the fields they override and are not versioned
+    fn read_drop_in(path: &Path) -> Result<serde_json::Value, ConfigError> {
+        let content = fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+        let format = ConfigFormat::from_path(path).ok_or_else(|| ConfigError::UnsupportedFormat(path.to_path_buf()))?;
+        let value: serde_json::Value = format.deserialize(path, &content)?;
+        if !value.is_object() {
+            return Err(ConfigError::Invalid(path.to_path_buf(), vec!["drop-in must be a table/object of fields".to_string()]));
+        }
+        // Catches unknown fields and wrong types with their position in this file
+        format.deserialize::<Self>(path, &content)?;
+        Ok(value)
+    }
+
+    // Upgrades the file to the current version, keeping the original as <file>.bak.
+    // Returns the version it was upgraded from and the new content, or None if already current.
+    pub fn migrate_file(path: &Path, dry_run: bool) -> Result<Option<(u32, String)>, ConfigError> {
+        let io_error = |e| ConfigError::Io(path.to_path_buf(), e);
+        let (value, from_version) = Self::read_file(path)?;
+        if from_version == CURRENT_CONFIG_VERSION {
+            return Ok(None);
+        }
+
+        let config = Self::from_value(path, value)?;
+
+        let migrated = config.to_string_for(path)?;
+        if !dry_run {
+            let mut backup = path.as_os_str().to_owned();
+            backup.push(".bak");
+            fs::copy(path, &backup).map_err(io_error)?;
+            fs::write(path, &migrated).map_err(io_error)?;
+        }
+        Ok(Some((from_version, migrated)))
+    }
+
+    pub fn json_schema() -> serde_json::Value {
+        serde_json::to_value(schemars::schema_for!(AppConfig)).expect("schema serializes")
+    }
+
+    pub fn validate(&self) -> Vec<String> {
+        self.problems().into_iter().map(|(_, problem)| problem).collect()
+    }
+
+    // Each problem together with the field it is about
+    fn problems(&self) -> Vec<(&'static str, String)> {
+        let mut problems = Vec::new();
+        let mut check = |ok: bool, field: &'static str, requirement: String| {
+            if !ok {
+                problems.push((field, format!("{} {}", field, requirement)));
+            }
+        };
+        check(
+            self.version == CURRENT_CONFIG_VERSION,
+            "version",
+            format!("must be {}", CURRENT_CONFIG_VERSION),
+        );
+        check(!self.service_name.trim().is_empty(), "service_name", "must not be empty".to_string());
+        check(!self.target_adapter_name.trim().is_empty(), "target_adapter_name", "must not be empty".to_string());
+        check(self.link_speed_threshold_bps.bps() > 0, "link_speed_threshold_bps", "must be greater than 0".to_string());
+        check(!self.wait_after_wake_secs.is_zero(), "wait_after_wake_secs", "must be greater than 0".to_string());
+        check(!self.restart_delay_secs.is_zero(), "restart_delay_secs", "must be greater than 0".to_string());
+        check(self.breaker_max_restarts > 0, "breaker_max_restarts", "must be at least 1".to_string());
+        check(!self.breaker_window_secs.is_zero(), "breaker_window_secs", "must be greater than 0".to_string());
+        // The endpoint has no authentication, so it must not be reachable from the network
+        check(
+            self.metrics.address().is_some_and(|address| address.ip().is_loopback()),
+            "metrics.listen",
+            "must be a loopback address with a port, e.g. 127.0.0.1:9185".to_string(),
+        );
+        if self.mqtt.enabled {
+            check(!self.mqtt.host.trim().is_empty(), "mqtt.host", "must not be empty".to_string());
+            check(self.mqtt.port > 0, "mqtt.port", "must be greater than 0".to_string());
+            check(!self.mqtt.topic_prefix.trim().is_empty(), "mqtt.topic_prefix", "must not be empty".to_string());
+        }
+        check(
+            self.mqtt.ca_file.is_none() || self.mqtt.tls,
+            "mqtt.ca_file",
+            "requires mqtt.tls = true".to_string(),
+        );
+        if self.syslog.enabled {
+            check(
+                self.syslog.has_valid_address(),
+                "syslog.address",
+                "must be host:port for udp and tcp, or a socket path for unix".to_string(),
+            );
+            check(!self.syslog.app_name.trim().is_empty(), "syslog.app_name", "must not be empty".to_string());
+        }
+        check(self.syslog.facility <= 23, "syslog.facility", "must be between 0 and 23".to_string());
+        if self.journald.enabled {
+            check(!self.journald.identifier.trim().is_empty(), "journald.identifier", "must not be empty".to_string());
+        }
+        check(!self.hooks.timeout_secs.is_zero(), "hooks.timeout_secs", "must be greater than 0".to_string());
+        for webhook in &self.webhooks {
+            check(
+                webhook.has_valid_url(),
+                "webhooks",
+                format!("url '{}' must start with http:// or https://", webhook.url),
+            );
+        }
+        problems
+    }
+
+    pub fn save(&self) -> Result<(), ConfigError> {
+        self.save_to(&Self::get_path())
+    }
+
+    // The format follows the file extension
+    pub fn save_to(&self, path: &Path) -> Result<(), ConfigError> {
+        let content = self.to_string_for(path)?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| ConfigError::Io(dir.to_path_buf(), e))?;
+        }
+        let mut file = File::create(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+        file.write_all(content.as_bytes()).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
         Ok(())
     }
+
+    pub fn to_string_for(&self, path: &Path) -> Result<String, ConfigError> {
+        let format = ConfigFormat::from_path(path).ok_or_else(|| ConfigError::UnsupportedFormat(path.to_path_buf()))?;
+        format
+            .serialize(self)
+            .map_err(|e| ConfigError::Invalid(path.to_path_buf(), vec![e]))
+    }
     
-    pub fn init() {
-        let config = Self::load();
-        let _ = CONFIG.set(config);
+    pub fn init() -> Result<(), ConfigError> {
+        Self::replace(Self::load()?);
+        Ok(())
     }
     
-    pub fn global() -> &'static AppConfig {
-        CONFIG.get().expect("Config not initialized")
+    pub fn global() -> Arc<AppConfig> {
+        CONFIG
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+            .expect("Config not initialized")
+    }
+
+    // Makes the defaults the global config unless one is already set, for tests
+    #[cfg(test)]
+    pub fn use_defaults() {
+        let mut config = CONFIG.write().unwrap_or_else(|e| e.into_inner());
+        if config.is_none() {
+            *config = Some(Arc::new(Self::default()));
+        }
+    }
+
+    fn replace(config: AppConfig) {
+        *CONFIG.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(config));
+    }
+
+    // Re-reads the config file and swaps it in if it parses and validates.
+    // On failure the current config stays active.
+    pub fn reload() -> Result<(), ConfigError> {
+        let config = Self::load()?;
+
+        let current = Self::global();
+        if config.service_name != current.service_name
+            || config.service_display_name != current.service_display_name
+        {
+            log::warn!("Service name changes only take effect after reinstalling the service.");
+        }
+        if config.metrics != current.metrics
+            || config.mqtt != current.mqtt
+            || config.syslog != current.syslog
+            || config.journald != current.journald
+        {
+            log::warn!("Metrics, MQTT, syslog and journald changes only take effect after restarting the service.");
+        }
+
+        Self::replace(config);
+        log::info!("Config reloaded from {:?}", Self::get_path());
+        Ok(())
+    }
+}
+
+// Polls the config file and drop-ins for changes and reloads them.
+pub struct ConfigWatcher {
+    stop: Arc<AtomicBool>,
+}
+
+impl ConfigWatcher {
+    pub fn start() -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_in_thread = stop.clone();
+
+        thread::spawn(move || {
+            let mut last_fingerprint = config_fingerprint();
+            while !stop_in_thread.load(Ordering::SeqCst) {
+                thread::sleep(WATCH_INTERVAL);
+                let fingerprint = config_fingerprint();
+                if fingerprint == last_fingerprint {
+                    continue;
+                }
+                last_fingerprint = fingerprint;
+                // Some editors delete and recreate the file, don't fall back to defaults meanwhile
+                if !AppConfig::get_path().exists() {
+                    continue;
+                }
+
+                log::info!("Config file changed, reloading...");
+                if let Err(e) = AppConfig::reload() {
+                    log::error!("Config reload failed, keeping current config: {}", e);
+                }
+            }
+        });
+
+        Self { stop }
+    }
+}
+
+impl Drop for ConfigWatcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
     }
-}
\ No newline at end of file
+}
+
+pub fn drop_in_files(main_path: &Path) -> Result<Vec<PathBuf>, ConfigError> {
+    let dir = main_path.with_file_name(DROP_IN_DIR);
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(ConfigError::Io(dir, e)),
+    };
+
+    // Editor backups and other stray files are skipped rather than rejected
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && ConfigFormat::from_path(path).is_some())
+        .filter(|path| !path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.')))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+// True if `path` names a field in `schema`, following nested sections through their $defs
+fn has_field(schema: &serde_json::Value, path: &[String]) -> bool {
+    let mut current = schema;
+    for key in path {
+        if let Some(name) = current.get("$ref").and_then(|r| r.as_str()).and_then(|r| r.strip_prefix("#/$defs/")) {
+            current = &schema["$defs"][name];
+        }
+        match current.get("properties").and_then(|properties| properties.get(key)) {
+            Some(field) => current = field,
+            None => return false,
+        }
+    }
+    !path.is_empty()
+}
+
+fn exe_dir() -> Option<PathBuf> {
+    env::current_exe().ok()?.parent().map(Path::to_path_buf)
+}
+
+// Next to the executable first, so existing installs keep working
+fn search_dirs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = exe_dir().into_iter().collect();
+    dirs.extend(system_config_dirs());
+    dirs
+}
+
+#[cfg(windows)]
+fn system_config_dirs() -> Vec<PathBuf> {
+    env::var_os("ProgramData")
+        .map(|dir| PathBuf::from(dir).join("Relink"))
+        .into_iter()
+        .collect()
+}
+
+#[cfg(unix)]
+fn system_config_dirs() -> Vec<PathBuf> {
+    xdg_config_dirs(
+        env::var_os("XDG_CONFIG_HOME"),
+        env::var_os("HOME"),
+        env::var("XDG_CONFIG_DIRS").ok(),
+    )
+}
+
+// The user's config directory, then the XDG system ones, then /etc/relink
+#[cfg(unix)]
+fn xdg_config_dirs(
+    config_home: Option<std::ffi::OsString>,
+    home: Option<std::ffi::OsString>,
+    config_dirs: Option<String>,
+) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    match config_home.filter(|dir| !dir.is_empty()) {
+        Some(dir) => dirs.push(PathBuf::from(dir).join("relink")),
+        None => dirs.extend(home.map(|home| PathBuf::from(home).join(".config").join("relink"))),
+    }
+    let config_dirs = config_dirs.unwrap_or_else(|| "/etc/xdg".to_string());
+    dirs.extend(config_dirs.split(':').filter(|dir| !dir.is_empty()).map(|dir| PathBuf::from(dir).join("relink")));
+    dirs.push(PathBuf::from("/etc/relink"));
+    dirs
+}
+
+// Where a new config is created. The executable's directory may be read-only
+// (Program Files, /usr/bin), so prefer the system-wide config directory.
+#[cfg(windows)]
+fn default_config_dir() -> PathBuf {
+    system_config_dirs().into_iter().next().or_else(exe_dir).unwrap_or_default()
+}
+
+#[cfg(unix)]
+fn default_config_dir() -> PathBuf {
+    PathBuf::from("/etc/relink")
+}
+
+// Modification times of the main file and every drop-in, so adding, removing or
+// editing any of them counts as a change
+fn config_fingerprint() -> Vec<(PathBuf, Option<SystemTime>)> {
+    let path = AppConfig::get_path();
+    let mut files = vec![path.clone()];
+    files.extend(drop_in_files(&path).unwrap_or_default());
+    files
+        .into_iter()
+        .map(|file| {
+            let modified = file.metadata().and_then(|m| m.modified()).ok();
+            (file, modified)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(field: &str) -> Vec<String> {
+        field.split('.').map(str::to_string).collect()
+    }
+
+    #[test]
+    fn has_field_follows_sections() {
+        let schema = AppConfig::json_schema();
+        for field in ["service_name", "mqtt.host", "hooks.pre_restart", "syslog.transport", "webhooks"] {
+            assert!(has_field(&schema, &path(field)), "{}", field);
+        }
+    }
+
+    #[test]
+    fn has_field_rejects_unknown_names() {
+        let schema = AppConfig::json_schema();
+        for field in ["debug", "mqtt.nope", "service_name.inner", "webhooks.url"] {
+            assert!(!has_field(&schema, &path(field)), "{}", field);
+        }
+        assert!(!has_field(&schema, &[]));
+    }
+
+    fn overridden(overrides: &[(&str, &str)]) -> Result<AppConfig, ConfigError> {
+        let mut layers = Layers::new(serde_json::to_value(AppConfig::default()).unwrap());
+        for (field, value) in overrides {
+            AppConfig::apply_override(&mut layers, &path(field), value, Source::Cli(field.to_string()))?;
+        }
+        Ok(serde_json::from_value(layers.into_parts().0).unwrap())
+    }
+
+    #[test]
+    fn numeric_overrides_of_optional_text_fields_stay_text() {
+        let config = overridden(&[
+            ("mqtt.password", "123456"),
+            ("mqtt.username", "true"),
+            ("hooks.pre_restart", "42"),
+            ("mqtt.port", "8883"),
+            ("service_name", "30"),
+        ])
+        .unwrap();
+        assert_eq!(config.mqtt.password.as_deref(), Some("123456"));
+        assert_eq!(config.mqtt.username.as_deref(), Some("true"));
+        assert_eq!(config.hooks.pre_restart.as_deref(), Some("42"));
+        assert_eq!(config.mqtt.port, 8883);
+        assert_eq!(config.service_name, "30");
+    }
+
+    #[test]
+    fn bad_overrides_report_the_typed_reading() {
+        let Err(ConfigError::Override(_, error)) = overridden(&[("mqtt.port", "-1")]) else {
+            panic!("expected an override error");
+        };
+        assert!(error.contains("-1"), "{}", error);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn xdg_config_dirs_prefer_config_home() {
+        let dirs = xdg_config_dirs(Some("/home/me/.cfg".into()), Some("/home/me".into()), Some("/a:/b".into()));
+        assert_eq!(
+            dirs,
+            ["/home/me/.cfg/relink", "/a/relink", "/b/relink", "/etc/relink"].map(PathBuf::from)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn xdg_config_dirs_fall_back_to_home_and_etc_xdg() {
+        let dirs = xdg_config_dirs(Some("".into()), Some("/home/me".into()), None);
+        assert_eq!(
+            dirs,
+            ["/home/me/.config/relink", "/etc/xdg/relink", "/etc/relink"].map(PathBuf::from)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn xdg_config_dirs_skip_empty_entries() {
+        let dirs = xdg_config_dirs(None, None, Some(":/a::".into()));
+        assert_eq!(dirs, ["/a/relink", "/etc/relink"].map(PathBuf::from));
+    }
+}
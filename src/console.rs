@@ -0,0 +1,164 @@
+use std::error::Error;
+use std::ffi::c_void;
+use std::io::{self, BufRead};
+use std::ptr;
+use std::sync::{Condvar, Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+use windows::core::BOOL;
+use windows::Win32::Foundation::{HANDLE, NO_ERROR};
+use windows::Win32::System::Console::{GetConsoleProcessList, SetConsoleCtrlHandler, CTRL_BREAK_EVENT, CTRL_CLOSE_EVENT, CTRL_C_EVENT};
+use windows::Win32::System::Power::{
+    PowerRegisterSuspendResumeNotification, PowerUnregisterSuspendResumeNotification,
+    DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS, HPOWERNOTIFY,
+};
+use windows::Win32::UI::WindowsAndMessaging::{DEVICE_NOTIFY_CALLBACK, PBT_APMRESUMEAUTOMATIC, PBT_APMRESUMESUSPEND};
+
+use crate::monitor::{EventSource, MonitorEvent, MonitorHandle};
+
+// The console control handler has no context argument
+static CTRL_C_TARGET: OnceLock<MonitorHandle> = OnceLock::new();
+// Set once the monitor has stopped, for the close handler to wait on
+static STOPPED: (Mutex<bool>, Condvar) = (Mutex::new(false), Condvar::new());
+// Windows ends the process about 5 seconds after a close event, or as soon as the handler returns
+const CLOSE_WAIT: Duration = Duration::from_millis(4500);
+
+const CONSOLE_COMMANDS: [(&str, MonitorEvent); 7] = [
+    ("wake", MonitorEvent::Wake),
+    ("check", MonitorEvent::Check),
+    ("pause", MonitorEvent::Pause),
+    ("resume", MonitorEvent::Resume),
+    ("reset-breaker", MonitorEvent::ResetBreaker),
+    ("reload", MonitorEvent::ReloadConfig),
+    ("stop", MonitorEvent::Stop),
+];
+
+// True when this process is the only one attached to its console, i.e. Windows created
+// the console for it because it was started from Explorer rather than a shell
+pub fn owns_console() -> bool {
+    let mut processes = [0u32; 2];
+    unsafe { GetConsoleProcessList(&mut processes) == 1 }
+}
+
+// Ctrl-C stops the monitor, and commands typed on stdin stand in for the SCM controls,
+// e.g. "wake" simulates a resume from sleep.
+pub struct ConsoleEvents;
+
+impl EventSource for ConsoleEvents {
+    fn start(&mut self, handle: MonitorHandle) -> Result<(), Box<dyn Error>> {
+        if CTRL_C_TARGET.set(handle.clone()).is_err() {
+            return Err("console events are already being delivered".into());
+        }
+        unsafe { SetConsoleCtrlHandler(Some(on_console_ctrl), true)? };
+
+        let names: Vec<&str> = CONSOLE_COMMANDS.iter().map(|(name, _)| *name).collect();
+        log::info!("Press Ctrl-C to stop. Commands: {}", names.join(", "));
+
+        // Not joined, the thread blocks on stdin until the process exits
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines() {
+                let Ok(line) = line else { break };
+                let command = line.trim();
+                if command.is_empty() {
+                    continue;
+                }
+                match CONSOLE_COMMANDS.iter().find(|(name, _)| *name == command) {
+                    Some((_, event)) => {
+                        if let Err(e) = handle.send(*event) {
+                            log::error!("Command '{}' failed: {}", command, e);
+                        }
+                    }
+                    None => log::warn!("Unknown command '{}'. Commands: {}", command, names.join(", ")),
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+// Dropped once the monitor has stopped
+impl Drop for ConsoleEvents {
+    fn drop(&mut self) {
+        let (stopped, changed) = &STOPPED;
+        *stopped.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        changed.notify_all();
+        let _ = unsafe { SetConsoleCtrlHandler(Some(on_console_ctrl), false) };
+    }
+}
+
+unsafe extern "system" fn on_console_ctrl(ctrl_type: u32) -> BOOL {
+    match ctrl_type {
+        CTRL_C_EVENT | CTRL_BREAK_EVENT | CTRL_CLOSE_EVENT => {
+            if let Some(handle) = CTRL_C_TARGET.get() {
+                log::info!("Ctrl-C received, stopping...");
+                let _ = handle.send(MonitorEvent::Stop);
+                // Returning lets Windows end the process, possibly in the middle of a restart
+                if ctrl_type == CTRL_CLOSE_EVENT {
+                    wait_until_stopped(CLOSE_WAIT);
+                }
+            }
+            true.into()
+        }
+        _ => false.into(),
+    }
+}
+
+fn wait_until_stopped(timeout: Duration) {
+    let (stopped, changed) = &STOPPED;
+    let stopped = stopped.lock().unwrap_or_else(|e| e.into_inner());
+    let _ = changed.wait_timeout_while(stopped, timeout, |stopped| !*stopped);
+}
+
+// Resume-from-sleep notifications for processes that don't receive SCM power events
+#[derive(Default)]
+pub struct PowerEvents {
+    registration: Option<HPOWERNOTIFY>,
+    // Both must stay at a fixed address while registered
+    parameters: Option<Box<DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS>>,
+    handle: Option<Box<MonitorHandle>>,
+}
+
+impl EventSource for PowerEvents {
+    fn start(&mut self, handle: MonitorHandle) -> Result<(), Box<dyn Error>> {
+        let handle = Box::new(handle);
+        let mut parameters = Box::new(DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS {
+            Callback: Some(on_power_event),
+            Context: &*handle as *const MonitorHandle as *mut c_void,
+        });
+
+        let mut registration: *mut c_void = ptr::null_mut();
+        let ret = unsafe {
+            PowerRegisterSuspendResumeNotification(
+                DEVICE_NOTIFY_CALLBACK,
+                HANDLE(&mut *parameters as *mut DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS as *mut c_void),
+                &mut registration,
+            )
+        };
+        if ret != NO_ERROR {
+            return Err(windows::core::Error::from_hresult(ret.to_hresult()).into());
+        }
+
+        self.registration = Some(HPOWERNOTIFY(registration as isize));
+        self.parameters = Some(parameters);
+        self.handle = Some(handle);
+        Ok(())
+    }
+}
+
+impl Drop for PowerEvents {
+    fn drop(&mut self) {
+        // No callback runs after unregistering, so the handle can be freed afterwards
+        if let Some(registration) = self.registration.take() {
+            let _ = unsafe { PowerUnregisterSuspendResumeNotification(registration) };
+        }
+    }
+}
+
+unsafe extern "system" fn on_power_event(context: *const c_void, event_type: u32, _setting: *const c_void) -> u32 {
+    if event_type == PBT_APMRESUMEAUTOMATIC || event_type == PBT_APMRESUMESUSPEND {
+        // Safety: context points at the handle boxed in PowerEvents, freed only after unregistering
+        let handle = unsafe { &*(context as *const MonitorHandle) };
+        let _ = handle.send(MonitorEvent::Wake);
+    }
+    NO_ERROR.0
+}
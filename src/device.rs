@@ -1,22 +1,271 @@
+use crate::breaker::CircuitBreaker;
 use crate::config::AppConfig;
+use crate::history::{self, RestartCause, RestartRecord, RestartStatus};
+use crate::hooks::{self, HookContext, HookPoint};
+use crate::lock::RestartLock;
+use crate::metrics;
+use crate::notify::{self, Notification};
+use crate::units::{Interval, LinkSpeed};
+use crate::worker::{RecoveryQueue, Trigger};
+use serde::{Deserialize, Serialize};
+#[cfg(windows)]
+use std::collections::HashMap;
+#[cfg(windows)]
+use std::ffi::c_void;
+#[cfg(windows)]
+use std::fs::OpenOptions;
+#[cfg(windows)]
+use std::os::windows::fs::OpenOptionsExt;
+#[cfg(windows)]
+use std::os::windows::io::AsRawHandle;
 use std::thread;
+use std::time::{Duration, Instant};
+#[cfg(windows)]
 use windows::core::HRESULT;
+#[cfg(windows)]
 use windows::Win32::Devices::DeviceAndDriverInstallation::{
     SetupDiCallClassInstaller, SetupDiDestroyDeviceInfoList, SetupDiEnumDeviceInfo,
     SetupDiGetClassDevsW, SetupDiGetDeviceRegistryPropertyW, SetupDiSetClassInstallParamsW,
     DICS_DISABLE, DICS_ENABLE, DICS_FLAG_GLOBAL, DIF_PROPERTYCHANGE, DIGCF_ALLCLASSES,
-    DIGCF_PRESENT, SP_CLASSINSTALL_HEADER, SP_DEVINFO_DATA, SP_PROPCHANGE_PARAMS,
-    SPDRP_FRIENDLYNAME, SPDRP_DEVICEDESC, SETUP_DI_REGISTRY_PROPERTY, SETUP_DI_STATE_CHANGE,
+    DIGCF_PRESENT, GUID_DEVCLASS_NET, SP_CLASSINSTALL_HEADER, SP_DEVINFO_DATA, SP_PROPCHANGE_PARAMS,
+    SPDRP_FRIENDLYNAME, SPDRP_DEVICEDESC, SPDRP_SERVICE, SETUP_DI_REGISTRY_PROPERTY, SETUP_DI_STATE_CHANGE,
 };
-use windows::Win32::Foundation::{GetLastError, ERROR_INVALID_DATA, NO_ERROR, ERROR_BUFFER_OVERFLOW};
-use windows::Win32::NetworkManagement::IpHelper::{GetAdaptersAddresses, GAA_FLAG_INCLUDE_GATEWAYS, IP_ADAPTER_ADDRESSES_LH};
-use std::time::Duration;
+#[cfg(windows)]
+use windows::Win32::Foundation::{GetLastError, ERROR_INVALID_DATA, NO_ERROR, ERROR_BUFFER_OVERFLOW, HANDLE};
+#[cfg(windows)]
+use windows::Win32::NetworkManagement::IpHelper::{
+    CancelMibChangeNotify2, GetAdaptersAddresses, NotifyIpInterfaceChange, GAA_FLAG_INCLUDE_GATEWAYS,
+    IF_TYPE_SOFTWARE_LOOPBACK, IF_TYPE_TUNNEL, IP_ADAPTER_ADDRESSES_LH, MIB_IPINTERFACE_ROW,
+    MIB_NOTIFICATION_TYPE,
+};
+#[cfg(windows)]
+use windows::Win32::NetworkManagement::Ndis::{
+    IfOperStatusDormant, IfOperStatusDown, IfOperStatusLowerLayerDown, IfOperStatusNotPresent,
+    IfOperStatusTesting, IfOperStatusUp, MediaDuplexStateFull, MediaDuplexStateHalf, IF_OPER_STATUS,
+    NDIS_LINK_SPEED, NET_IF_MEDIA_DUPLEX_STATE, OID_GEN_MAX_LINK_SPEED, OID_GEN_MEDIA_DUPLEX_STATE,
+};
+#[cfg(windows)]
+use windows::Win32::Networking::WinSock::AF_UNSPEC;
+#[cfg(windows)]
+use windows::Win32::Storage::FileSystem::{FILE_SHARE_READ, FILE_SHARE_WRITE};
+#[cfg(windows)]
+use windows::Win32::System::IO::DeviceIoControl;
 
+#[cfg(windows)]
 const ADAPTER_BUFFER_SIZE: u32 = 15000;
+#[cfg(windows)]
 const MAX_ADAPTER_RETRIES: i32 = 3;
-const BYTES_TO_MBPS_DIVISOR: u64 = 1_000_000;
+// CTL_CODE(FILE_DEVICE_PHYSICAL_NETCARD, 0, METHOD_OUT_DIRECT, FILE_ANY_ACCESS), not exported by the windows crate
+#[cfg(windows)]
+const IOCTL_NDIS_QUERY_GLOBAL_STATS: u32 = 0x0017_0002;
+// A re-enabled adapter needs a few seconds to renegotiate its link
+const VERIFY_TIMEOUT: Duration = Duration::from_secs(30);
+const VERIFY_POLL_INTERVAL: Duration = Duration::from_secs(2);
+// Disabling and enabling the device, on top of restart_delay_secs
+const DEVICE_TOGGLE_MARGIN: Duration = Duration::from_secs(10);
+// How restart_and_verify brings the link back, reported in metrics
+const RESTART_STRATEGY: &str = "disable_enable";
+// IANA interface types, as reported in AdapterInfo::if_type
+#[cfg(not(windows))]
+const IF_TYPE_SOFTWARE_LOOPBACK: u32 = 24;
+#[cfg(not(windows))]
+const IF_TYPE_TUNNEL: u32 = 131;
+
+// Errors from the OS adapter APIs
+#[cfg(windows)]
+pub type OsError = windows::core::Error;
+#[cfg(not(windows))]
+pub type OsError = std::io::Error;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdapterInfo {
+    pub friendly_name: String,
+    pub description: String,
+    pub guid: String,
+    pub index: u32,
+    pub luid: u64,
+    pub mac: String,
+    pub if_type: u32,
+    pub status: String,
+    pub receive_speed: LinkSpeed,
+    pub transmit_speed: LinkSpeed,
+    pub max_speed: Option<LinkSpeed>,
+    pub duplex: String,
+    pub driver: Option<String>,
+}
+
+impl AdapterInfo {
+    // Loopback and tunnel interfaces can't be degraded links
+    pub fn is_physical(&self) -> bool {
+        self.if_type != IF_TYPE_SOFTWARE_LOOPBACK && self.if_type != IF_TYPE_TUNNEL
+    }
+
+    // Same rule get_link_speed uses to pick the monitored adapter
+    pub fn matches(&self, adapter_name: &str) -> bool {
+        self.friendly_name.contains(adapter_name) || self.description.contains(adapter_name)
+    }
+}
+
+#[cfg(windows)]
+pub fn get_link_speed(adapter_name: &str) -> Result<Option<LinkSpeed>, OsError> {
+    find_adapter(adapter_name, |adapter| LinkSpeed(adapter.ReceiveLinkSpeed))
+}
+
+#[cfg(windows)]
+// Highest speed the adapter can negotiate, as reported by its driver
+pub fn get_max_link_speed(adapter_name: &str) -> Result<Option<LinkSpeed>, OsError> {
+    let Some(guid) = find_adapter(adapter_name, adapter_guid)? else {
+        return Ok(None);
+    };
+    query_max_link_speed(&guid).map(Some)
+}
+
+#[cfg(windows)]
+// Every adapter GetAdaptersAddresses reports, in its order. Driver details are
+// best effort and left empty when the driver doesn't answer.
+pub fn list_adapters() -> Result<Vec<AdapterInfo>, OsError> {
+    let mut adapters = Vec::new();
+    walk_adapters(|adapter| {
+        let mac_length = (adapter.PhysicalAddressLength as usize).min(adapter.PhysicalAddress.len());
+        adapters.push(AdapterInfo {
+            friendly_name: unsafe { adapter.FriendlyName.to_string().unwrap_or_default() },
+            description: unsafe { adapter.Description.to_string().unwrap_or_default() },
+            guid: adapter_guid(adapter),
+            index: unsafe { adapter.Anonymous1.Anonymous.IfIndex },
+            luid: unsafe { adapter.Luid.Value },
+            mac: adapter.PhysicalAddress[..mac_length]
+                .iter()
+                .map(|byte| format!("{:02X}", byte))
+                .collect::<Vec<_>>()
+                .join("-"),
+            if_type: adapter.IfType,
+            status: oper_status_name(adapter.OperStatus).to_string(),
+            receive_speed: LinkSpeed(adapter.ReceiveLinkSpeed),
+            transmit_speed: LinkSpeed(adapter.TransmitLinkSpeed),
+            max_speed: None,
+            duplex: "unknown".to_string(),
+            driver: None,
+        });
+        false
+    })?;
+
+    let drivers = unsafe { net_device_drivers() }.unwrap_or_default();
+    for adapter in adapters.iter_mut() {
+        adapter.driver = drivers.get(&adapter.description).cloned();
+        if !adapter.is_physical() {
+            continue;
+        }
+        adapter.max_speed = query_max_link_speed(&adapter.guid).ok();
+        let duplex = match query_ndis_oid::<NET_IF_MEDIA_DUPLEX_STATE>(&adapter.guid, OID_GEN_MEDIA_DUPLEX_STATE) {
+            Ok(state) if state == MediaDuplexStateFull => "full",
+            Ok(state) if state == MediaDuplexStateHalf => "half",
+            _ => "unknown",
+        };
+        adapter.duplex = duplex.to_string();
+    }
+    Ok(adapters)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdapterReport {
+    #[serde(flatten)]
+    pub adapter: AdapterInfo,
+    // Config field whose value selects this adapter, if any
+    pub matched_by: Option<String>,
+}
 
-pub fn get_link_speed(adapter_name: &str) -> Result<Option<u64>, windows::core::Error> {
+// All adapters, marking the one `target_adapter_name` selects. Like get_link_speed,
+// only the first match counts.
+pub fn adapter_reports(target_adapter_name: &str) -> Result<Vec<AdapterReport>, OsError> {
+    let adapters = list_adapters()?;
+    let selected = adapters.iter().position(|adapter| adapter.matches(target_adapter_name));
+    Ok(adapters
+        .into_iter()
+        .enumerate()
+        .map(|(index, adapter)| AdapterReport {
+            adapter,
+            matched_by: (Some(index) == selected).then(|| "target_adapter_name".to_string()),
+        })
+        .collect())
+}
+
+#[cfg(windows)]
+fn oper_status_name(status: IF_OPER_STATUS) -> &'static str {
+    const NAMES: [(IF_OPER_STATUS, &str); 6] = [
+        (IfOperStatusUp, "up"),
+        (IfOperStatusDown, "down"),
+        (IfOperStatusTesting, "testing"),
+        (IfOperStatusDormant, "dormant"),
+        (IfOperStatusNotPresent, "not present"),
+        (IfOperStatusLowerLayerDown, "lower layer down"),
+    ];
+    NAMES.iter().find(|(value, _)| *value == status).map_or("unknown", |(_, name)| name)
+}
+
+#[cfg(windows)]
+fn adapter_guid(adapter: &IP_ADAPTER_ADDRESSES_LH) -> String {
+    unsafe { adapter.AdapterName.to_string().unwrap_or_default() }
+}
+
+#[cfg(windows)]
+fn query_max_link_speed(guid: &str) -> Result<LinkSpeed, windows::core::Error> {
+    let speed = query_ndis_oid::<NDIS_LINK_SPEED>(guid, OID_GEN_MAX_LINK_SPEED)?;
+    Ok(LinkSpeed(speed.RcvLinkSpeed.max(speed.XmitLinkSpeed)))
+}
+
+#[cfg(windows)]
+// Asks the adapter's driver for a single OID through its \\.\{guid} device
+fn query_ndis_oid<T: Default>(guid: &str, oid: u32) -> Result<T, windows::core::Error> {
+    let device = OpenOptions::new()
+        .access_mode(0)
+        .share_mode(FILE_SHARE_READ.0 | FILE_SHARE_WRITE.0)
+        .open(format!(r"\\.\{}", guid))
+        .map_err(|e| windows::core::Error::from_hresult(HRESULT::from_win32(e.raw_os_error().unwrap_or(0) as u32)))?;
+
+    let mut value = T::default();
+    let mut bytes_returned = 0u32;
+    unsafe {
+        DeviceIoControl(
+            HANDLE(device.as_raw_handle()),
+            IOCTL_NDIS_QUERY_GLOBAL_STATS,
+            Some(&oid as *const u32 as *const c_void),
+            size_of::<u32>() as u32,
+            Some(&mut value as *mut T as *mut c_void),
+            size_of::<T>() as u32,
+            Some(&mut bytes_returned),
+            None,
+        )?
+    };
+    Ok(value)
+}
+
+#[cfg(windows)]
+pub fn adapter_description(adapter_name: &str) -> Result<Option<String>, OsError> {
+    find_adapter(adapter_name, |adapter| unsafe { adapter.Description.to_string().unwrap_or_default() })
+}
+
+#[cfg(windows)]
+fn find_adapter<T>(
+    adapter_name: &str,
+    extract: impl Fn(&IP_ADAPTER_ADDRESSES_LH) -> T,
+) -> Result<Option<T>, windows::core::Error> {
+    let mut found = None;
+    walk_adapters(|adapter| {
+        let description = unsafe { adapter.Description.to_string().unwrap_or_default() };
+        let friendly_name = unsafe { adapter.FriendlyName.to_string().unwrap_or_default() };
+
+        if friendly_name.contains(adapter_name) || description.contains(adapter_name) {
+            found = Some(extract(adapter));
+            return true;
+        }
+        false
+    })?;
+    Ok(found)
+}
+
+#[cfg(windows)]
+// Calls `visit` for each adapter until it returns true
+fn walk_adapters(mut visit: impl FnMut(&IP_ADAPTER_ADDRESSES_LH) -> bool) -> Result<(), windows::core::Error> {
     let mut out_buf_len: u32 = ADAPTER_BUFFER_SIZE;
     
     for _ in 0..MAX_ADAPTER_RETRIES {
@@ -44,20 +293,64 @@ pub fn get_link_speed(adapter_name: &str) -> Result<Option<u64>, windows::core::
         let mut curr_ptr = p_adapter_addresses;
         while !curr_ptr.is_null() {
             let curr = unsafe { &*curr_ptr };
-            let description = unsafe { curr.Description.to_string().unwrap_or_default() };
-            let friendly_name = unsafe { curr.FriendlyName.to_string().unwrap_or_default() };
-
-            if friendly_name.contains(adapter_name) || description.contains(adapter_name) {
-                return Ok(Some(curr.ReceiveLinkSpeed));
+            if visit(curr) {
+                break;
             }
             curr_ptr = curr.Next;
         }
         break;
     }
-    Ok(None)
+    Ok(())
 }
 
-pub unsafe fn restart_device_by_name(target_name: &str, restart_delay_secs: u64) -> windows::core::Result<bool> {
+#[cfg(windows)]
+pub struct LinkWatcher {
+    handle: HANDLE,
+    _queue: Box<RecoveryQueue>,
+}
+
+#[cfg(windows)]
+impl LinkWatcher {
+    pub fn start(queue: RecoveryQueue) -> Result<Self, OsError> {
+        let queue = Box::new(queue);
+        let mut handle = HANDLE::default();
+        let ret = unsafe {
+            NotifyIpInterfaceChange(
+                AF_UNSPEC,
+                Some(on_interface_change),
+                Some(&*queue as *const RecoveryQueue as *const c_void),
+                false,
+                &mut handle,
+            )
+        };
+        if ret != NO_ERROR {
+            return Err(windows::core::Error::from_hresult(HRESULT::from_win32(ret.0)));
+        }
+        Ok(Self { handle, _queue: queue })
+    }
+}
+
+#[cfg(windows)]
+impl Drop for LinkWatcher {
+    fn drop(&mut self) {
+        // Blocks until any running callback has returned, so the queue outlives it
+        let _ = unsafe { CancelMibChangeNotify2(self.handle) };
+    }
+}
+
+#[cfg(windows)]
+unsafe extern "system" fn on_interface_change(
+    context: *const c_void,
+    _row: *const MIB_IPINTERFACE_ROW,
+    _notification_type: MIB_NOTIFICATION_TYPE,
+) {
+    // Safety: context points at the queue boxed in LinkWatcher, freed only after cancellation
+    let queue = unsafe { &*(context as *const RecoveryQueue) };
+    queue.submit(Trigger::LinkChange);
+}
+
+#[cfg(windows)]
+pub unsafe fn restart_device_by_name(target_name: &str, restart_delay: Interval) -> Result<bool, OsError> {
     // Safety check
     let dev_info = unsafe {
         SetupDiGetClassDevsW(
@@ -95,7 +388,7 @@ pub unsafe fn restart_device_by_name(target_name: &str, restart_delay_secs: u64)
                 log::info!("Disabling device...");
                 set_device_state(dev_info, &mut dev_info_data, DICS_DISABLE)?;
                 
-                thread::sleep(Duration::from_secs(restart_delay_secs));
+                thread::sleep(restart_delay.duration());
 
                 log::info!("Enabling device...");
                 set_device_state(dev_info, &mut dev_info_data, DICS_ENABLE)?;
@@ -110,6 +403,36 @@ pub unsafe fn restart_device_by_name(target_name: &str, restart_delay_secs: u64)
     Ok(found)
 }
 
+#[cfg(windows)]
+// Device name (as matched by restart_device_by_name) -> driver service of each present network device
+unsafe fn net_device_drivers() -> windows::core::Result<HashMap<String, String>> {
+    let dev_info = unsafe { SetupDiGetClassDevsW(Some(&GUID_DEVCLASS_NET), None, None, DIGCF_PRESENT)? };
+
+    let mut dev_info_data = SP_DEVINFO_DATA {
+        cbSize: size_of::<SP_DEVINFO_DATA>() as u32,
+        ..Default::default()
+    };
+
+    let mut drivers = HashMap::new();
+    let mut i = 0;
+    while unsafe { SetupDiEnumDeviceInfo(dev_info, i, &mut dev_info_data).is_ok() } {
+        i += 1;
+
+        let name = unsafe {
+            get_device_property(dev_info, &mut dev_info_data, SPDRP_FRIENDLYNAME)
+                .or_else(|_| get_device_property(dev_info, &mut dev_info_data, SPDRP_DEVICEDESC))
+        };
+        let service = unsafe { get_device_property(dev_info, &mut dev_info_data, SPDRP_SERVICE) };
+        if let (Ok(name), Ok(service)) = (name, service) {
+            drivers.insert(name, service);
+        }
+    }
+
+    unsafe { SetupDiDestroyDeviceInfoList(dev_info)? };
+    Ok(drivers)
+}
+
+#[cfg(windows)]
 unsafe fn get_device_property(
     dev_info: windows::Win32::Devices::DeviceAndDriverInstallation::HDEVINFO,
     dev_info_data: &mut SP_DEVINFO_DATA,
@@ -154,6 +477,7 @@ unsafe fn get_device_property(
     Ok(String::from_utf16_lossy(&wide_buffer[..len]))
 }
 
+#[cfg(windows)]
 unsafe fn set_device_state(
     dev_info: windows::Win32::Devices::DeviceAndDriverInstallation::HDEVINFO,
     dev_info_data: &mut SP_DEVINFO_DATA,
@@ -191,11 +515,233 @@ unsafe fn set_device_state(
     }
 }
 
-pub fn check_and_fix_network(force_check: bool) {
+// Adapters are only managed through the Windows APIs. Other builds, used for development,
+// see no adapters and fail every adapter operation.
+#[cfg(not(windows))]
+fn unsupported() -> OsError {
+    OsError::new(std::io::ErrorKind::Unsupported, "network adapters can only be managed on Windows")
+}
+
+#[cfg(not(windows))]
+pub fn get_link_speed(_adapter_name: &str) -> Result<Option<LinkSpeed>, OsError> {
+    Err(unsupported())
+}
+
+#[cfg(not(windows))]
+pub fn get_max_link_speed(_adapter_name: &str) -> Result<Option<LinkSpeed>, OsError> {
+    Err(unsupported())
+}
+
+#[cfg(not(windows))]
+pub fn list_adapters() -> Result<Vec<AdapterInfo>, OsError> {
+    Err(unsupported())
+}
+
+#[cfg(not(windows))]
+pub fn adapter_description(_adapter_name: &str) -> Result<Option<String>, OsError> {
+    Err(unsupported())
+}
+
+#[cfg(not(windows))]
+pub unsafe fn restart_device_by_name(_target_name: &str, _restart_delay: Interval) -> Result<bool, OsError> {
+    Err(unsupported())
+}
+
+#[cfg(not(windows))]
+pub struct LinkWatcher {
+    _queue: RecoveryQueue,
+}
+
+#[cfg(not(windows))]
+impl LinkWatcher {
+    pub fn start(_queue: RecoveryQueue) -> Result<Self, OsError> {
+        Err(unsupported())
+    }
+}
+
+// Restarts the configured adapter, or the one `adapter` matches, on an operator's request.
+// Waits for a restart already in progress rather than skipping.
+pub fn manual_restart(adapter: Option<String>) -> Option<RestartRecord> {
+    let mut config = (*AppConfig::global()).clone();
+    if let Some(adapter) = adapter {
+        config.target_adapter_name = adapter;
+    }
+
+    let speed_before = get_link_speed(&config.target_adapter_name).ok().flatten();
+    log::info!("Manually restarting '{}'...", config.target_adapter_name);
+    // Manual restarts bypass the circuit breaker, so each is a first attempt. Waiting for the
+    // longest a restart can take queues this one behind an automatic restart in progress.
+    let lock_wait = max_restart_duration(&config);
+    let record = restart_adapter(&config, RestartCause::Manual, Trigger::Manual, 1, speed_before, lock_wait)?;
+    notify::publish(Notification::restart(&record));
+    Some(record)
+}
+
+// Restarts the adapter `config.target_adapter_name` selects, then waits for the link to come
+// back above the threshold. Returns None without restarting if another restart holds the lock
+// for longer than `lock_wait`, and a Failed record if the lock can't be taken at all. Every
+// attempt made under the lock is recorded in the restart history, including those the
+// pre-restart hook vetoes.
+pub fn restart_adapter(
+    config: &AppConfig,
+    cause: RestartCause,
+    trigger: Trigger,
+    attempt: u32,
+    speed_before: Option<LinkSpeed>,
+    lock_wait: Duration,
+) -> Option<RestartRecord> {
+    let matcher = &config.target_adapter_name;
+    let mut record = RestartRecord::new(matcher, cause, speed_before);
+    // SetupAPI needs the exact device name, the matcher may only be part of it
+    if let Ok(Some(description)) = adapter_description(matcher) {
+        record.adapter = description;
+    }
+
+    let _lock = match RestartLock::acquire(lock_wait) {
+        Ok(Some(lock)) => lock,
+        Ok(None) => {
+            log::warn!("Another restart of the adapter is in progress, skipping.");
+            return None;
+        }
+        Err(e) => {
+            // Restarting without the lock could race another process restarting the same adapter
+            log::error!("Failed to take the restart lock, not restarting: {}", e);
+            record.error = Some(format!("failed to take the restart lock: {}", e));
+            return Some(record);
+        }
+    };
+    restart_and_verify(config, record, trigger, attempt)
+}
+
+fn restart_and_verify(config: &AppConfig, mut record: RestartRecord, trigger: Trigger, attempt: u32) -> Option<RestartRecord> {
+    let mut context = HookContext {
+        adapter: &record.adapter,
+        trigger,
+        attempt,
+        speed_before: record.speed_before,
+        speed_after: None,
+        status: None,
+    };
+    if let Err(reason) = hooks::run(&config.hooks, HookPoint::PreRestart, &context) {
+        log::warn!(adapter = record.adapter.as_str(), action = "vetoed"; "Restart vetoed: {}", reason);
+        record.status = RestartStatus::Vetoed;
+        record.error = Some(reason);
+        append_history(&record);
+        return Some(record);
+    }
+
+    let succeeded = match unsafe { restart_device_by_name(&record.adapter, config.restart_delay_secs) } {
+        Ok(true) => {
+            log::info!("Device restart sequence completed, verifying link speed...");
+            record.speed_after = wait_for_link(&config.target_adapter_name, config.link_speed_threshold_bps);
+            if record.speed_after.is_some_and(|speed| speed > config.link_speed_threshold_bps) {
+                let speed = record.speed_after.unwrap_or_default();
+                log::info!(adapter = record.adapter.as_str(), speed_bps = speed.bps(), action = "recovered"; "Link recovered at {}.", speed);
+                record.status = RestartStatus::Recovered;
+                true
+            } else {
+                log::warn!(adapter = record.adapter.as_str(), action = "still_degraded"; "Link still at or below {} after restart.", config.link_speed_threshold_bps);
+                record.status = RestartStatus::StillDegraded;
+                false
+            }
+        }
+        Ok(false) => {
+            log::error!(adapter = record.adapter.as_str(), action = "device_not_found"; "Device '{}' not found.", record.adapter);
+            record.status = RestartStatus::DeviceNotFound;
+            false
+        }
+        Err(e) => {
+            log::error!(adapter = record.adapter.as_str(), action = "failed"; "Failed to restart device: {:?}", e);
+            record.error = Some(format!("failed to restart device: {}", e));
+            false
+        }
+    };
+    metrics::record_restart(RESTART_STRATEGY, succeeded);
+
+    context.speed_after = record.speed_after;
+    context.status = Some(record.status);
+    let point = if succeeded { HookPoint::PostSuccess } else { HookPoint::PostFailure };
+    if let Err(reason) = hooks::run(&config.hooks, point, &context) {
+        log::warn!("{}", reason);
+    }
+
+    append_history(&record);
+    Some(record)
+}
+
+fn append_history(record: &RestartRecord) {
+    if let Err(e) = history::append(record) {
+        log::warn!("Failed to record restart in {:?}: {}", history::history_path(), e);
+    }
+}
+
+// Longest a restart can take: both hooks at their timeout, the device toggle and the verification
+pub fn max_restart_duration(config: &AppConfig) -> Duration {
+    config.hooks.timeout_secs.duration() * 2
+        + config.restart_delay_secs.duration()
+        + DEVICE_TOGGLE_MARGIN
+        + VERIFY_TIMEOUT
+}
+
+// Polls until the link is above the threshold or VERIFY_TIMEOUT passes, returning the last speed seen
+fn wait_for_link(matcher: &str, threshold: LinkSpeed) -> Option<LinkSpeed> {
+    let deadline = Instant::now() + VERIFY_TIMEOUT;
+    loop {
+        let speed = get_link_speed(matcher).ok().flatten();
+        if speed.is_some_and(|speed| speed > threshold) || Instant::now() >= deadline {
+            return speed;
+        }
+        thread::sleep(VERIFY_POLL_INTERVAL);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum CheckOutcome {
+    Healthy,
+    Fixed,
+    NotFixed { reason: String },
+    AdapterNotFound,
+    Error { message: String },
+}
+
+impl CheckOutcome {
+    // Exit codes of `relink check`. 2 is left for usage errors like the other commands.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CheckOutcome::Healthy => 0,
+            CheckOutcome::Error { .. } => 1,
+            CheckOutcome::Fixed => 3,
+            CheckOutcome::NotFixed { .. } => 4,
+            CheckOutcome::AdapterNotFound => 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckReport {
+    pub adapter: String,
+    pub threshold: LinkSpeed,
+    pub speed: Option<LinkSpeed>,
+    #[serde(flatten)]
+    pub outcome: CheckOutcome,
+    // Set when the check restarted the adapter
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restart: Option<RestartRecord>,
+}
+
+impl CheckReport {
+    pub fn is_degraded(&self) -> bool {
+        self.speed.is_some_and(|speed| speed <= self.threshold)
+    }
+}
+
+// Only forced triggers may restart the adapter, others just report a degraded link
+pub fn check_and_fix_network(trigger: Trigger, fix_allowed: bool, breaker: &mut CircuitBreaker) -> CheckReport {
     let config = AppConfig::global();
+    let force_check = trigger.is_forced();
     let target_adapter = &config.target_adapter_name;
     let threshold = config.link_speed_threshold_bps;
-    let restart_delay = config.restart_delay_secs;
 
     if force_check {
         log::info!("Performing forced network check (e.g., after wake)...");
@@ -203,27 +749,68 @@ pub fn check_and_fix_network(force_check: bool) {
         log::info!("Performing routine network check...");
     }
 
-    match get_link_speed(target_adapter) {
+    let mut report = CheckReport {
+        adapter: target_adapter.clone(),
+        threshold,
+        speed: None,
+        outcome: CheckOutcome::Healthy,
+        restart: None,
+    };
+    let not_fixed = |reason: &str| CheckOutcome::NotFixed { reason: reason.to_string() };
+
+    report.outcome = match get_link_speed(target_adapter) {
         Ok(Some(speed)) => {
-            let speed_mbps = speed / BYTES_TO_MBPS_DIVISOR;
-            log::info!("Current Link Speed: {} Mbps", speed_mbps);
+            log::info!(adapter = target_adapter.as_str(), speed_bps = speed.bps(), action = "check"; "Current Link Speed: {}", speed);
+            report.speed = Some(speed);
             
             if speed <= threshold {
-                 if force_check {
-                     log::warn!("Speed detected as <= {} Mbps AFTER WAKE. Initiating restart sequence.", threshold / BYTES_TO_MBPS_DIVISOR);
-                     match unsafe { restart_device_by_name(target_adapter, restart_delay) } {
-                        Ok(true) => log::info!("Device restart sequence completed successfully."),
-                        Ok(false) => log::error!("Device '{}' not found.", target_adapter),
-                        Err(e) => log::error!("Failed to restart device: {:?}", e),
-                    }
+                 if !force_check {
+                     log::warn!("Speed detected as <= {}, but not a wake event. Ignoring to prevent random restarts during normal use.", threshold);
+                     not_fixed("not a wake event")
+                 } else if !fix_allowed {
+                     log::warn!("Speed detected as <= {}, but fixes are disabled for this check (paused or not requested). Skipping restart.", threshold);
+                     not_fixed("fixes are disabled")
+                 } else if breaker.is_open() {
+                     log::error!("Speed detected as <= {}, but the circuit breaker is open ({} restarts within {}). Skipping restart.", threshold, config.breaker_max_restarts, config.breaker_window_secs);
+                     not_fixed("circuit breaker is open")
                  } else {
-                     log::warn!("Speed detected as <= {} Mbps, but not a wake event. Ignoring to prevent random restarts during normal use.", threshold / BYTES_TO_MBPS_DIVISOR);
+                     log::warn!(adapter = target_adapter.as_str(), speed_bps = speed.bps(), action = "restart"; "Speed detected as <= {} AFTER WAKE. Initiating restart sequence.", threshold);
+                     // Don't wait for a manual restart in progress, the next check will see its result
+                     let attempt = breaker.recent() + 1;
+                     match restart_adapter(&config, RestartCause::Automatic, trigger, attempt, Some(speed), Duration::ZERO) {
+                        Some(record) => {
+                            // A vetoed restart left the adapter alone, so it doesn't count
+                            if record.status != RestartStatus::Vetoed {
+                                breaker.record_restart();
+                            }
+                            let outcome = match record.status {
+                                RestartStatus::Recovered => CheckOutcome::Fixed,
+                                RestartStatus::StillDegraded => not_fixed("link still degraded after restart"),
+                                RestartStatus::DeviceNotFound => not_fixed("device not found for restart"),
+                                RestartStatus::Vetoed => not_fixed("restart vetoed by the pre-restart hook"),
+                                RestartStatus::Failed => CheckOutcome::Error {
+                                    message: record.error.clone().unwrap_or_default(),
+                                },
+                            };
+                            report.restart = Some(record);
+                            outcome
+                        }
+                        None => not_fixed("another restart is in progress"),
+                    }
                  }
             } else {
-                log::info!("Speed is normal (>{} Mbps). No action required.", threshold / BYTES_TO_MBPS_DIVISOR);
+                log::info!("Speed is normal (>{}). No action required.", threshold);
+                CheckOutcome::Healthy
             }
         }
-        Ok(None) => log::error!("Adapter '{}' not found in network interfaces.", target_adapter),
-        Err(e) => log::error!("Failed to retrieve adapter info: {:?}", e),
-    }
-}
\ No newline at end of file
+        Ok(None) => {
+            log::error!("Adapter '{}' not found in network interfaces.", target_adapter);
+            CheckOutcome::AdapterNotFound
+        }
+        Err(e) => {
+            log::error!("Failed to retrieve adapter info: {:?}", e);
+            CheckOutcome::Error { message: format!("failed to retrieve adapter info: {}", e) }
+        }
+    };
+    report
+}
@@ -0,0 +1,75 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::path::Path;
+
+use crate::config::ConfigError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl ConfigFormat {
+    // Lookup order when several config files sit next to each other
+    pub const ALL: [(ConfigFormat, &'static str); 4] = [
+        (ConfigFormat::Json, "json"),
+        (ConfigFormat::Toml, "toml"),
+        (ConfigFormat::Yaml, "yaml"),
+        (ConfigFormat::Yaml, "yml"),
+    ];
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        Self::ALL
+            .iter()
+            .find(|(_, ext)| *ext == extension)
+            .map(|(format, _)| *format)
+    }
+
+    pub fn deserialize<T: DeserializeOwned>(self, path: &Path, content: &str) -> Result<T, ConfigError> {
+        match self {
+            ConfigFormat::Json => serde_json::from_str(content).map_err(|e| {
+                parse_error(path, Some((e.line(), e.column())), e.to_string())
+            }),
+            ConfigFormat::Toml => toml::from_str(content).map_err(|e| {
+                let position = e.span().map(|span| line_column(content, span.start));
+                parse_error(path, position, e.message().to_string())
+            }),
+            ConfigFormat::Yaml => serde_yaml::from_str(content).map_err(|e| {
+                let position = e.location().map(|location| (location.line(), location.column()));
+                parse_error(path, position, e.to_string())
+            }),
+        }
+    }
+
+    pub fn serialize<T: Serialize>(self, value: &T) -> Result<String, String> {
+        match self {
+            ConfigFormat::Json => serde_json::to_string_pretty(value).map_err(|e| e.to_string()),
+            ConfigFormat::Toml => toml::to_string_pretty(value).map_err(|e| e.to_string()),
+            ConfigFormat::Yaml => serde_yaml::to_string(value).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+fn parse_error(path: &Path, position: Option<(usize, usize)>, message: String) -> ConfigError {
+    // The position is reported separately, drop the copy some parsers append
+    let message = match message.rfind(" at line ") {
+        Some(index) if position.is_some() => message[..index].to_string(),
+        _ => message,
+    };
+    ConfigError::Parse {
+        path: path.to_path_buf(),
+        position,
+        message,
+    }
+}
+
+// 1-based line and column of a byte offset
+fn line_column(content: &str, offset: usize) -> (usize, usize) {
+    let before = content.get(..offset).unwrap_or(content);
+    let line = before.matches('\n').count() + 1;
+    let column = before.chars().rev().take_while(|&c| c != '\n').count() + 1;
+    (line, column)
+}
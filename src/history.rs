@@ -0,0 +1,148 @@
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::units::LinkSpeed;
+
+const HISTORY_FILENAME: &str = "relink_history.jsonl";
+// Oldest entries are dropped beyond this
+const MAX_HISTORY_ENTRIES: usize = 200;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RestartCause {
+    Automatic,
+    Manual,
+}
+
+impl RestartCause {
+    pub fn name(self) -> &'static str {
+        match self {
+            RestartCause::Automatic => "automatic",
+            RestartCause::Manual => "manual",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RestartStatus {
+    Recovered,
+    StillDegraded,
+    DeviceNotFound,
+    Failed,
+    // The pre-restart hook refused, the adapter was left alone
+    Vetoed,
+}
+
+impl RestartStatus {
+    pub fn name(self) -> &'static str {
+        match self {
+            RestartStatus::Recovered => "recovered",
+            RestartStatus::StillDegraded => "still_degraded",
+            RestartStatus::DeviceNotFound => "device_not_found",
+            RestartStatus::Failed => "failed",
+            RestartStatus::Vetoed => "vetoed",
+        }
+    }
+}
+
+// One adapter restart, by the service or from the command line
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RestartRecord {
+    // Seconds since the Unix epoch
+    pub time: u64,
+    pub adapter: String,
+    pub cause: RestartCause,
+    pub status: RestartStatus,
+    pub speed_before: Option<LinkSpeed>,
+    pub speed_after: Option<LinkSpeed>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl RestartRecord {
+    pub fn new(adapter: &str, cause: RestartCause, speed_before: Option<LinkSpeed>) -> Self {
+        Self {
+            time: unix_now(),
+            adapter: adapter.to_string(),
+            cause,
+            status: RestartStatus::Failed,
+            speed_before,
+            speed_after: None,
+            error: None,
+        }
+    }
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+// UTC, e.g. 2024-05-01 13:37:00Z
+pub fn format_time(unix_secs: u64) -> String {
+    let days = (unix_secs / 86_400) as i64;
+    let secs = unix_secs % 86_400;
+    // Civil date from days since 1970-01-01, proleptic Gregorian calendar
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3_600,
+        secs % 3_600 / 60,
+        secs % 60
+    )
+}
+
+// Next to the executable and its log, so the service and the CLI share it
+pub fn history_path() -> PathBuf {
+    let mut path = env::current_exe().unwrap_or_default();
+    path.set_file_name(HISTORY_FILENAME);
+    path
+}
+
+// Oldest first. Lines that don't parse are skipped.
+pub fn load() -> io::Result<Vec<RestartRecord>> {
+    let file = match File::open(history_path()) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut records = Vec::new();
+    for line in BufReader::new(file).lines() {
+        if let Ok(record) = serde_json::from_str(&line?) {
+            records.push(record);
+        }
+    }
+    Ok(records)
+}
+
+// Callers hold the restart lock, so writers never interleave
+pub fn append(record: &RestartRecord) -> io::Result<()> {
+    let mut records = load()?;
+    records.push(record.clone());
+    let skip = records.len().saturating_sub(MAX_HISTORY_ENTRIES);
+
+    let path = history_path();
+    let temp_path = path.with_extension("jsonl.tmp");
+    let mut file = File::create(&temp_path)?;
+    for record in &records[skip..] {
+        let line = serde_json::to_string(record).map_err(io::Error::other)?;
+        writeln!(file, "{}", line)?;
+    }
+    drop(file);
+    fs::rename(temp_path, path)
+}
@@ -0,0 +1,214 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Read};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::history::RestartStatus;
+use crate::units::{Interval, LinkSpeed};
+use crate::worker::Trigger;
+
+// Variables passed to hooks. Excluded from RELINK_* config overrides, so a hook can run
+// relink itself.
+pub const HOOK_ENV_PREFIX: &str = "RELINK_HOOK_";
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Commands run around adapter restarts, through cmd /C (sh -c on development builds for other platforms)
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct HooksConfig {
+    /// Runs before a restart. The restart only happens if it exits with 0 in time.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pre_restart: Option<String>,
+    /// Runs after a restart brought the link back
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub post_success: Option<String>,
+    /// Runs after a restart that failed or left the link degraded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub post_failure: Option<String>,
+    /// How long a hook may run before it is killed (e.g. "30s")
+    pub timeout_secs: Interval,
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        Self {
+            pre_restart: None,
+            post_success: None,
+            post_failure: None,
+            timeout_secs: Interval::from_secs(30),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookPoint {
+    PreRestart,
+    PostSuccess,
+    PostFailure,
+}
+
+impl HookPoint {
+    fn name(self) -> &'static str {
+        match self {
+            HookPoint::PreRestart => "pre_restart",
+            HookPoint::PostSuccess => "post_success",
+            HookPoint::PostFailure => "post_failure",
+        }
+    }
+
+    fn command(self, config: &HooksConfig) -> Option<&str> {
+        match self {
+            HookPoint::PreRestart => config.pre_restart.as_deref(),
+            HookPoint::PostSuccess => config.post_success.as_deref(),
+            HookPoint::PostFailure => config.post_failure.as_deref(),
+        }
+    }
+}
+
+// What a hook is told about the restart it runs around
+pub struct HookContext<'a> {
+    pub adapter: &'a str,
+    pub trigger: Trigger,
+    // Restarts within the circuit breaker window, this one included
+    pub attempt: u32,
+    pub speed_before: Option<LinkSpeed>,
+    pub speed_after: Option<LinkSpeed>,
+    // Outcome of the restart, for the post hooks
+    pub status: Option<RestartStatus>,
+}
+
+impl HookContext<'_> {
+    fn env(&self, point: HookPoint) -> Vec<(String, String)> {
+        let speed = |speed: Option<LinkSpeed>| speed.map(|speed| speed.bps().to_string()).unwrap_or_default();
+        [
+            ("POINT", point.name().to_string()),
+            ("ADAPTER", self.adapter.to_string()),
+            ("TRIGGER", self.trigger.name().replace(' ', "_")),
+            ("ATTEMPT", self.attempt.to_string()),
+            ("OLD_SPEED_BPS", speed(self.speed_before)),
+            ("NEW_SPEED_BPS", speed(self.speed_after)),
+            ("STATUS", self.status.map(RestartStatus::name).unwrap_or_default().to_string()),
+        ]
+        .into_iter()
+        .map(|(name, value)| (format!("{}{}", HOOK_ENV_PREFIX, name), value))
+        .collect()
+    }
+}
+
+// Runs the hook configured for `point`, if any. Err describes why it didn't succeed:
+// it couldn't start, exited non-zero or timed out.
+pub fn run(config: &HooksConfig, point: HookPoint, context: &HookContext) -> Result<(), String> {
+    let Some(command) = point.command(config).filter(|command| !command.trim().is_empty()) else {
+        return Ok(());
+    };
+    log::info!("Running {} hook: {}", point.name(), command);
+
+    let mut child = shell(command)
+        .envs(context.env(point))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("{} hook failed to start: {}", point.name(), e))?;
+    forward_output(child.stdout.take(), point);
+    forward_output(child.stderr.take(), point);
+
+    let timeout = config.timeout_secs.duration();
+    match wait_with_timeout(&mut child, timeout) {
+        Ok(Some(status)) if status.success() => Ok(()),
+        Ok(Some(status)) => Err(format!("{} hook exited with {}", point.name(), status)),
+        Ok(None) => {
+            let _ = child.kill();
+            let _ = child.wait();
+            Err(format!("{} hook timed out after {}", point.name(), config.timeout_secs))
+        }
+        Err(e) => Err(format!("{} hook failed: {}", point.name(), e)),
+    }
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    use std::os::windows::process::CommandExt;
+    // cmd has its own quoting rules, pass the line through untouched
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").raw_arg(command);
+    shell
+}
+
+#[cfg(not(windows))]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+fn wait_with_timeout(child: &mut Child, timeout: Duration) -> std::io::Result<Option<std::process::ExitStatus>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if Instant::now() >= deadline {
+            return Ok(None);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+// Logs the hook's output line by line, so a chatty hook can't fill the pipe and stall.
+// Not joined: a process the hook left running may keep the pipe open.
+fn forward_output(stream: Option<impl Read + Send + 'static>, point: HookPoint) {
+    let Some(stream) = stream else { return };
+    thread::spawn(move || {
+        for line in BufReader::new(stream).lines().map_while(Result::ok) {
+            log::info!("[{} hook] {}", point.name(), line);
+        }
+    });
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn context() -> HookContext<'static> {
+        HookContext {
+            adapter: "Intel(R) Ethernet",
+            trigger: Trigger::LinkChange,
+            attempt: 2,
+            speed_before: Some(LinkSpeed(100_000_000)),
+            speed_after: None,
+            status: None,
+        }
+    }
+
+    fn pre_restart(command: &str) -> Result<(), String> {
+        let config = HooksConfig {
+            pre_restart: Some(command.to_string()),
+            timeout_secs: Interval::from_secs(2),
+            ..HooksConfig::default()
+        };
+        run(&config, HookPoint::PreRestart, &context())
+    }
+
+    #[test]
+    fn unset_or_blank_hooks_succeed() {
+        assert!(run(&HooksConfig::default(), HookPoint::PreRestart, &context()).is_ok());
+        assert!(pre_restart("  ").is_ok());
+    }
+
+    #[test]
+    fn passes_the_context_in_the_environment() {
+        let check = r#"test "$RELINK_HOOK_POINT" = pre_restart && test "$RELINK_HOOK_ADAPTER" = "Intel(R) Ethernet" \
+            && test "$RELINK_HOOK_TRIGGER" = link_change && test "$RELINK_HOOK_ATTEMPT" = 2 \
+            && test "$RELINK_HOOK_OLD_SPEED_BPS" = 100000000 && test -z "$RELINK_HOOK_NEW_SPEED_BPS""#;
+        assert_eq!(pre_restart(check), Ok(()));
+    }
+
+    #[test]
+    fn failing_hooks_report_why() {
+        assert!(pre_restart("exit 3").unwrap_err().starts_with("pre_restart hook exited with"));
+        assert_eq!(pre_restart("sleep 10").unwrap_err(), "pre_restart hook timed out after 2s");
+    }
+}
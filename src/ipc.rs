@@ -0,0 +1,229 @@
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Local endpoint of the control API, named after the service so several installs don't collide
+#[cfg(windows)]
+pub fn endpoint_name(service_name: &str) -> String {
+    format!(r"\\.\pipe\Relink-{}", service_name.replace('\\', "_"))
+}
+
+#[cfg(unix)]
+pub fn endpoint_name(service_name: &str) -> String {
+    let dir = std::env::var("XDG_RUNTIME_DIR").unwrap_or_else(|_| "/run/relink".to_string());
+    format!("{}/{}.sock", dir.trim_end_matches('/'), service_name.replace('/', "_"))
+}
+
+#[cfg(windows)]
+pub use self::windows_pipe::{connect, IpcListener, IpcStream};
+
+#[cfg(unix)]
+pub use self::unix_socket::{connect, IpcListener, IpcStream};
+
+#[cfg(windows)]
+mod windows_pipe {
+    use std::fs::{File, OpenOptions};
+    use std::io;
+    use std::os::windows::io::FromRawHandle;
+    use std::thread;
+    use std::time::Duration;
+    use windows::core::HSTRING;
+    use windows::Win32::Foundation::{CloseHandle, ERROR_PIPE_BUSY, ERROR_PIPE_CONNECTED, INVALID_HANDLE_VALUE};
+    use windows::Win32::Storage::FileSystem::{FILE_FLAG_FIRST_PIPE_INSTANCE, PIPE_ACCESS_DUPLEX};
+    use windows::Win32::System::Pipes::{
+        ConnectNamedPipe, CreateNamedPipeW, PIPE_READMODE_BYTE, PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_BYTE,
+        PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+    };
+
+    use crate::security::{SecurityAttributes, ADMIN_ONLY_SDDL};
+
+    const PIPE_BUFFER_SIZE: u32 = 64 * 1024;
+    const CONNECT_RETRIES: u32 = 20;
+    const CONNECT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+    pub type IpcStream = File;
+
+    pub struct IpcListener {
+        name: HSTRING,
+        attributes: SecurityAttributes,
+        first_instance: bool,
+    }
+
+    impl IpcListener {
+        // Only SYSTEM and administrators may connect, remote clients are rejected
+        pub fn bind(name: &str) -> io::Result<Self> {
+            let attributes = SecurityAttributes::from_sddl(ADMIN_ONLY_SDDL).map_err(io::Error::other)?;
+            Ok(Self {
+                name: HSTRING::from(name),
+                attributes,
+                first_instance: true,
+            })
+        }
+
+        // Blocks until a client connects. Each connection gets its own pipe instance.
+        pub fn accept(&mut self) -> io::Result<IpcStream> {
+            let mut open_mode = PIPE_ACCESS_DUPLEX;
+            // Fails if someone else already owns the name, so a squatter can't impersonate the service
+            if self.first_instance {
+                open_mode |= FILE_FLAG_FIRST_PIPE_INSTANCE;
+            }
+            let handle = unsafe {
+                CreateNamedPipeW(
+                    &self.name,
+                    open_mode,
+                    PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                    PIPE_UNLIMITED_INSTANCES,
+                    PIPE_BUFFER_SIZE,
+                    PIPE_BUFFER_SIZE,
+                    0,
+                    Some(self.attributes.as_ptr()),
+                )
+            };
+            if handle == INVALID_HANDLE_VALUE {
+                return Err(io::Error::last_os_error());
+            }
+            self.first_instance = false;
+
+            if let Err(e) = unsafe { ConnectNamedPipe(handle, None) } {
+                // The client connected between creating and waiting, which is fine
+                if e.code() != ERROR_PIPE_CONNECTED.to_hresult() {
+                    let _ = unsafe { CloseHandle(handle) };
+                    return Err(io::Error::other(e));
+                }
+            }
+            Ok(unsafe { File::from_raw_handle(handle.0) })
+        }
+    }
+
+    // All instances are busy only for the moment between a connect and the next accept
+    pub fn connect(name: &str) -> io::Result<IpcStream> {
+        let mut attempts = 0;
+        loop {
+            match OpenOptions::new().read(true).write(true).open(name) {
+                Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY.0 as i32) && attempts < CONNECT_RETRIES => {
+                    attempts += 1;
+                    thread::sleep(CONNECT_RETRY_DELAY);
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+mod unix_socket {
+    use std::fs;
+    use std::io;
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::{Path, PathBuf};
+
+    pub type IpcStream = UnixStream;
+
+    pub struct IpcListener {
+        listener: UnixListener,
+        path: PathBuf,
+    }
+
+    impl IpcListener {
+        // Only the owner (the service's user) may connect
+        pub fn bind(name: &str) -> io::Result<Self> {
+            let path = PathBuf::from(name);
+            if let Some(dir) = path.parent() {
+                fs::DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+            }
+            // A stale socket from a crashed run would make bind fail
+            if UnixStream::connect(&path).is_err() {
+                let _ = fs::remove_file(&path);
+            }
+            let listener = UnixListener::bind(&path)?;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+            Ok(Self { listener, path })
+        }
+
+        pub fn accept(&mut self) -> io::Result<IpcStream> {
+            self.listener.accept().map(|(stream, _)| stream)
+        }
+    }
+
+    impl Drop for IpcListener {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+
+    pub fn connect(name: &str) -> io::Result<IpcStream> {
+        UnixStream::connect(Path::new(name))
+    }
+}
+
+// Ends an accept loop blocked in accept(): sets the flag it checks after each connection,
+// then makes `connect` the connection that wakes it. Used by the control API and metrics servers.
+pub fn stop_accept_loop<T>(stop: &AtomicBool, connect: impl FnOnce() -> io::Result<T>) {
+    stop.store(true, Ordering::SeqCst);
+    let _ = connect();
+}
+
+// True when the error means nothing is listening, as opposed to a failure talking to it
+pub fn is_not_running(error: &io::Error) -> bool {
+    matches!(error.kind(), io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::{Read, Write};
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::UnixListener;
+
+    fn test_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("relink-ipc-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn socket_is_private_to_its_owner() {
+        let dir = test_dir("private");
+        let path = dir.join("control.sock");
+        let _listener = IpcListener::bind(path.to_str().unwrap()).unwrap();
+        assert_eq!(fs::metadata(&dir).unwrap().permissions().mode() & 0o777, 0o700);
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn connections_carry_data_both_ways() {
+        let dir = test_dir("echo");
+        let name = dir.join("control.sock").to_string_lossy().into_owned();
+        let mut listener = IpcListener::bind(&name).unwrap();
+        let mut client = connect(&name).unwrap();
+        let mut server = listener.accept().unwrap();
+
+        client.write_all(b"ping").unwrap();
+        let mut buffer = [0u8; 4];
+        server.read_exact(&mut buffer).unwrap();
+        assert_eq!(&buffer, b"ping");
+        server.write_all(b"pong").unwrap();
+        client.read_exact(&mut buffer).unwrap();
+        assert_eq!(&buffer, b"pong");
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn stale_sockets_are_replaced_and_removed_on_drop() {
+        let dir = test_dir("stale");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("control.sock");
+        // Left behind by a listener that is gone, nothing accepts on it any more
+        drop(UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+
+        let listener = IpcListener::bind(path.to_str().unwrap()).unwrap();
+        assert!(connect(path.to_str().unwrap()).is_ok());
+        drop(listener);
+        assert!(!path.exists());
+        assert!(is_not_running(&connect(path.to_str().unwrap()).unwrap_err()));
+        let _ = fs::remove_dir_all(dir);
+    }
+}
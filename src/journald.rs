@@ -0,0 +1,217 @@
+#[cfg(unix)]
+pub use sink::JournaldSink;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// systemd journal output, Linux only
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct JournaldConfig {
+    /// Send log lines to the journal, with ADAPTER, SPEED_BPS and ACTION fields where known
+    pub enabled: bool,
+    /// SYSLOG_IDENTIFIER of each entry
+    pub identifier: String,
+    /// The journal's native socket
+    pub socket: PathBuf,
+}
+
+impl Default for JournaldConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            identifier: "relink".to_string(),
+            socket: PathBuf::from("/run/systemd/journal/socket"),
+        }
+    }
+}
+
+// The native protocol is a unix datagram socket
+#[cfg(unix)]
+mod sink {
+    use log::{LevelFilter, Log, Metadata, Record};
+    use std::io;
+    use std::os::unix::net::UnixDatagram;
+
+    use super::JournaldConfig;
+    use crate::logger;
+    use crate::syslog::severity;
+
+    pub struct JournaldSink {
+        level: LevelFilter,
+        identifier: String,
+        socket: UnixDatagram,
+    }
+
+    impl JournaldSink {
+        pub fn connect(config: &JournaldConfig, level: LevelFilter) -> io::Result<Self> {
+            let socket = UnixDatagram::unbound()?;
+            socket.connect(&config.socket)?;
+            Ok(Self {
+                level,
+                identifier: config.identifier.clone(),
+                socket,
+            })
+        }
+
+        // One entry in the native protocol: a datagram of FIELD=value lines
+        fn entry(&self, record: &Record) -> Vec<u8> {
+            let mut entry = Vec::new();
+            add_field(&mut entry, "MESSAGE", &record.args().to_string());
+            add_field(&mut entry, "PRIORITY", &severity(record.level()).to_string());
+            add_field(&mut entry, "SYSLOG_IDENTIFIER", &self.identifier);
+            add_field(&mut entry, "TARGET", record.target());
+            if let Some(file) = record.file() {
+                add_field(&mut entry, "CODE_FILE", file);
+            }
+            if let Some(line) = record.line() {
+                add_field(&mut entry, "CODE_LINE", &line.to_string());
+            }
+            for (key, value) in logger::fields(record) {
+                if let Some(name) = field_name(&key) {
+                    add_field(&mut entry, &name, &value);
+                }
+            }
+            entry
+        }
+    }
+
+    impl Log for JournaldSink {
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            metadata.level() <= self.level
+        }
+
+        fn log(&self, record: &Record) {
+            if !self.enabled(record.metadata()) {
+                return;
+            }
+            // Entries too big for one datagram are dropped, ours never get close
+            let _ = self.socket.send(&self.entry(record));
+        }
+
+        fn flush(&self) {}
+    }
+
+    // Values with newlines use the binary form: name, newline, little-endian u64 length, value
+    fn add_field(entry: &mut Vec<u8>, name: &str, value: &str) {
+        entry.extend_from_slice(name.as_bytes());
+        if value.contains('\n') {
+            entry.push(b'\n');
+            entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        } else {
+            entry.push(b'=');
+        }
+        entry.extend_from_slice(value.as_bytes());
+        entry.push(b'\n');
+    }
+
+    // Journal field names are uppercase letters, digits and underscores, not starting with
+    // an underscore or digit. None for keys that can't be made into one.
+    fn field_name(key: &str) -> Option<String> {
+        let name: String = key
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+            .collect();
+        let name = name.trim_start_matches(|c: char| c == '_' || c.is_ascii_digit());
+        (!name.is_empty()).then(|| name.to_string())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use log::Level;
+        use std::fs;
+        use std::path::PathBuf;
+        use std::time::Duration;
+
+        // Splits an entry back into fields, reading both the text and binary forms
+        fn parse(mut entry: &[u8]) -> Vec<(String, String)> {
+            let mut fields = Vec::new();
+            while !entry.is_empty() {
+                let end = entry.iter().position(|&b| b == b'=' || b == b'\n').unwrap();
+                let name = String::from_utf8(entry[..end].to_vec()).unwrap();
+                let (value, rest) = if entry[end] == b'=' {
+                    let len = entry[end + 1..].iter().position(|&b| b == b'\n').unwrap();
+                    (&entry[end + 1..end + 1 + len], &entry[end + 1 + len..])
+                } else {
+                    let start = end + 1 + 8;
+                    let len = u64::from_le_bytes(entry[end + 1..start].try_into().unwrap()) as usize;
+                    (&entry[start..start + len], &entry[start + len..])
+                };
+                assert_eq!(rest[0], b'\n');
+                fields.push((name, String::from_utf8(value.to_vec()).unwrap()));
+                entry = &rest[1..];
+            }
+            fields
+        }
+
+        fn value<'a>(fields: &'a [(String, String)], name: &str) -> Option<&'a str> {
+            fields.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+        }
+
+        fn journal(name: &str) -> (JournaldSink, UnixDatagram, PathBuf) {
+            let dir = std::env::temp_dir().join(format!("relink-journald-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            let config = JournaldConfig {
+                enabled: true,
+                socket: dir.join("socket"),
+                ..JournaldConfig::default()
+            };
+            let server = UnixDatagram::bind(&config.socket).unwrap();
+            server.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            (JournaldSink::connect(&config, LevelFilter::Info).unwrap(), server, dir)
+        }
+
+        fn receive(server: &UnixDatagram) -> Vec<u8> {
+            let mut buffer = [0; 4096];
+            let len = server.recv(&mut buffer).unwrap();
+            buffer[..len].to_vec()
+        }
+
+        #[test]
+        fn encodes_text_and_binary_fields() {
+            let mut entry = Vec::new();
+            add_field(&mut entry, "MESSAGE", "Restarted.");
+            add_field(&mut entry, "MESSAGE", "two\nlines");
+            let mut expected = b"MESSAGE=Restarted.\nMESSAGE\n".to_vec();
+            expected.extend_from_slice(&9u64.to_le_bytes());
+            expected.extend_from_slice(b"two\nlines\n");
+            assert_eq!(entry, expected);
+        }
+
+        #[test]
+        fn field_names_follow_journal_rules() {
+            assert_eq!(field_name("speed_bps").as_deref(), Some("SPEED_BPS"));
+            assert_eq!(field_name("adapter.name").as_deref(), Some("ADAPTER_NAME"));
+            assert_eq!(field_name("_1action").as_deref(), Some("ACTION"));
+            assert_eq!(field_name("__"), None);
+        }
+
+        #[test]
+        fn sends_entries_with_adapter_fields() {
+            let (sink, server, dir) = journal("fields");
+            let fields = [("adapter", "Intel(R) Ethernet"), ("speed_bps", "100000000"), ("action", "restart")];
+            sink.log(&Record::builder().args(format_args!("Filtered out.")).level(Level::Debug).build());
+            sink.log(
+                &Record::builder()
+                    .args(format_args!("Link speed dropped.\nRestarting."))
+                    .level(Level::Warn)
+                    .target("relink::worker")
+                    .key_values(&fields)
+                    .build(),
+            );
+
+            let fields = parse(&receive(&server));
+            assert_eq!(value(&fields, "MESSAGE"), Some("Link speed dropped.\nRestarting."));
+            assert_eq!(value(&fields, "PRIORITY"), Some("4"));
+            assert_eq!(value(&fields, "SYSLOG_IDENTIFIER"), Some("relink"));
+            assert_eq!(value(&fields, "TARGET"), Some("relink::worker"));
+            assert_eq!(value(&fields, "ADAPTER"), Some("Intel(R) Ethernet"));
+            assert_eq!(value(&fields, "SPEED_BPS"), Some("100000000"));
+            assert_eq!(value(&fields, "ACTION"), Some("restart"));
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::config::CONFIG_PATH_ENV;
+use crate::hooks::HOOK_ENV_PREFIX;
+
+pub const ENV_PREFIX: &str = "RELINK_";
+// Separates nested keys in variable names, e.g. RELINK_SECTION__FIELD
+const ENV_NESTING: &str = "__";
+
+// Where a config value came from. Later sources win: defaults, file, environment, CLI.
+#[derive(Debug, Clone)]
+pub enum Source {
+    Default,
+    File(PathBuf),
+    Env(String),
+    Cli(String),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Default => f.write_str("default"),
+            Source::File(path) => write!(f, "file {}", path.display()),
+            Source::Env(name) => write!(f, "env {}", name),
+            Source::Cli(key) => write!(f, "--set {}", key),
+        }
+    }
+}
+
+// Dotted field path -> source of its current value
+pub type Provenance = BTreeMap<String, Source>;
+
+pub struct Layers {
+    value: Value,
+    provenance: Provenance,
+}
+
+impl Layers {
+    pub fn new(defaults: Value) -> Self {
+        let mut provenance = Provenance::new();
+        record_leaves(&defaults, "", &Source::Default, &mut provenance);
+        Self { value: defaults, provenance }
+    }
+
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
+    pub fn into_parts(self) -> (Value, Provenance) {
+        (self.value, self.provenance)
+    }
+
+    // Objects are merged key by key, anything else (including lists) is replaced
+    pub fn merge(&mut self, overlay: Value, source: &Source) {
+        merge_value(&mut self.value, overlay, "", source, &mut self.provenance);
+    }
+
+    // Sets a single field from a string. The current value decides how the string is
+    // read, so "30" stays a string for text fields but becomes a number for numeric ones.
+    pub fn set(&mut self, path: &[String], raw: &str, source: Source) -> Result<(), String> {
+        self.insert(path, source, |current| typed_value(current, raw))
+    }
+
+    // Sets a single field to `raw` as is, for text fields whose current value is null
+    // and so doesn't tell how to read it
+    pub fn set_text(&mut self, path: &[String], raw: &str, source: Source) -> Result<(), String> {
+        self.insert(path, source, |_| Value::String(raw.to_string()))
+    }
+
+    fn insert(&mut self, path: &[String], source: Source, value: impl FnOnce(Option<&Value>) -> Value) -> Result<(), String> {
+        let (leaf, parents) = path.split_last().ok_or("empty field name")?;
+        let mut current = &mut self.value;
+        for key in parents {
+            let Value::Object(map) = current else {
+                return Err(format!("'{}' is not a section", key));
+            };
+            current = map.entry(key.clone()).or_insert_with(|| Value::Object(Map::new()));
+        }
+        let Value::Object(map) = current else {
+            return Err(format!("cannot set '{}' inside a non-section value", leaf));
+        };
+
+        let value = value(map.get(leaf));
+        map.insert(leaf.clone(), value);
+        self.provenance.insert(path.join("."), source);
+        Ok(())
+    }
+}
+
+// RELINK_<FIELD>=value pairs among `vars`, as (variable, field path, value). The path
+// may not name a field, other tools can use the prefix too.
+pub fn env_overrides(vars: impl IntoIterator<Item = (String, String)>) -> Vec<(String, Vec<String>, String)> {
+    let mut overrides: Vec<_> = vars
+        .into_iter()
+        .filter(|(name, _)| name != CONFIG_PATH_ENV && !name.starts_with(HOOK_ENV_PREFIX))
+        .filter_map(|(name, value)| {
+            let field = name.strip_prefix(ENV_PREFIX)?.to_ascii_lowercase();
+            let path = field.split(ENV_NESTING).map(str::to_string).collect();
+            Some((name, path, value))
+        })
+        .collect();
+    overrides.sort();
+    overrides
+}
+
+fn typed_value(current: Option<&Value>, raw: &str) -> Value {
+    match current {
+        Some(Value::String(_)) => Value::String(raw.to_string()),
+        _ => serde_json::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string())),
+    }
+}
+
+fn merge_value(base: &mut Value, overlay: Value, prefix: &str, source: &Source, provenance: &mut Provenance) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                let path = join_path(prefix, &key);
+                match base.get_mut(&key) {
+                    Some(existing) => merge_value(existing, value, &path, source, provenance),
+                    None => {
+                        record_leaves(&value, &path, source, provenance);
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => {
+            provenance.retain(|key, _| !is_within(key, prefix));
+            record_leaves(&overlay, prefix, source, provenance);
+            *base = overlay;
+        }
+    }
+}
+
+fn record_leaves(value: &Value, prefix: &str, source: &Source, provenance: &mut Provenance) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (key, value) in map {
+                record_leaves(value, &join_path(prefix, key), source, provenance);
+            }
+        }
+        _ => {
+            provenance.insert(prefix.to_string(), source.clone());
+        }
+    }
+}
+
+fn join_path(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", prefix, key)
+    }
+}
+
+fn is_within(key: &str, prefix: &str) -> bool {
+    key == prefix || key.strip_prefix(prefix).is_some_and(|rest| rest.starts_with('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(names: &[&str]) -> Vec<(String, String)> {
+        names.iter().map(|name| (name.to_string(), "1".to_string())).collect()
+    }
+
+    #[test]
+    fn env_overrides_split_nested_fields() {
+        let overrides = env_overrides(vars(&["RELINK_MQTT__HOST", "RELINK_SERVICE_NAME", "PATH"]));
+        let paths: Vec<_> = overrides.into_iter().map(|(_, path, _)| path).collect();
+        assert_eq!(paths, vec![vec!["mqtt".to_string(), "host".to_string()], vec!["service_name".to_string()]]);
+    }
+
+    #[test]
+    fn env_overrides_skip_config_path_and_hook_variables() {
+        let overrides = env_overrides(vars(&[CONFIG_PATH_ENV, "RELINK_HOOK_ADAPTER"]));
+        assert!(overrides.is_empty());
+    }
+}
@@ -0,0 +1,94 @@
+#[cfg(windows)]
+pub use self::windows_mutex::RestartLock;
+
+#[cfg(unix)]
+pub use self::lock_file::RestartLock;
+
+#[cfg(windows)]
+mod windows_mutex {
+    use std::io;
+    use std::time::Duration;
+    use windows::core::{w, PCWSTR};
+    use windows::Win32::Foundation::{CloseHandle, HANDLE, WAIT_ABANDONED, WAIT_OBJECT_0, WAIT_TIMEOUT};
+    use windows::Win32::System::Threading::{CreateMutexW, ReleaseMutex, WaitForSingleObject};
+
+    use crate::security::{SecurityAttributes, ADMIN_ONLY_SDDL};
+
+    // Machine-wide, so the service and any console instance see the same mutex. Whichever of
+    // them creates it first applies the admin-only ACL.
+    const RESTART_MUTEX_NAME: PCWSTR = w!("Global\\RelinkAdapterRestart");
+
+    // Held while an adapter is being restarted. Released on drop, which must happen on
+    // the thread that acquired it.
+    pub struct RestartLock {
+        handle: HANDLE,
+    }
+
+    impl RestartLock {
+        // Returns None if another process still holds the lock after `wait`
+        pub fn acquire(wait: Duration) -> io::Result<Option<Self>> {
+            let attributes = SecurityAttributes::from_sddl(ADMIN_ONLY_SDDL)?;
+            let handle = unsafe { CreateMutexW(Some(attributes.as_ptr()), false, RESTART_MUTEX_NAME)? };
+            let millis = u32::try_from(wait.as_millis()).unwrap_or(u32::MAX);
+
+            match unsafe { WaitForSingleObject(handle, millis) } {
+                // An abandoned mutex means the previous holder died mid-restart, the lock is ours now
+                WAIT_OBJECT_0 | WAIT_ABANDONED => Ok(Some(Self { handle })),
+                WAIT_TIMEOUT => {
+                    let _ = unsafe { CloseHandle(handle) };
+                    Ok(None)
+                }
+                _ => {
+                    let error = windows::core::Error::from_thread();
+                    let _ = unsafe { CloseHandle(handle) };
+                    Err(error.into())
+                }
+            }
+        }
+    }
+
+    impl Drop for RestartLock {
+        fn drop(&mut self) {
+            unsafe {
+                let _ = ReleaseMutex(self.handle);
+                let _ = CloseHandle(self.handle);
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+mod lock_file {
+    use std::env;
+    use std::fs::{File, TryLockError};
+    use std::io;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    const LOCK_FILENAME: &str = "relink_restart.lock";
+    const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+    // Held while an adapter is being restarted, an advisory lock on a file next to the
+    // executable. Released on drop, or by the OS if the holder dies.
+    pub struct RestartLock {
+        _file: File,
+    }
+
+    impl RestartLock {
+        // Returns None if another process still holds the lock after `wait`
+        pub fn acquire(wait: Duration) -> io::Result<Option<Self>> {
+            let mut path = env::current_exe()?;
+            path.set_file_name(LOCK_FILENAME);
+            let file = File::options().create(true).truncate(false).write(true).open(path)?;
+            let deadline = Instant::now() + wait;
+            loop {
+                match file.try_lock() {
+                    Ok(()) => return Ok(Some(Self { _file: file })),
+                    Err(TryLockError::WouldBlock) if Instant::now() < deadline => thread::sleep(POLL_INTERVAL),
+                    Err(TryLockError::WouldBlock) => return Ok(None),
+                    Err(TryLockError::Error(e)) => return Err(e),
+                }
+            }
+        }
+    }
+}
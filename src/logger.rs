@@ -1,8 +1,47 @@
 use std::env;
 use std::fs::File;
+use std::sync::OnceLock;
+use log::kv::{self, Key, Value, VisitSource};
+use log::{Log, Metadata, Record};
 use simplelog::{CombinedLogger, Config, LevelFilter, TermLogger, WriteLogger, TerminalMode, ColorChoice, SharedLogger};
 
-pub fn init_logger() {
+use crate::config::AppConfig;
+use crate::syslog::SyslogSink;
+
+// Sinks from the config file, added once it has loaded
+static SINKS: OnceLock<Vec<Box<dyn Log>>> = OnceLock::new();
+
+struct Logger {
+    local: Box<CombinedLogger>,
+}
+
+impl Logger {
+    fn sinks() -> &'static [Box<dyn Log>] {
+        SINKS.get().map_or(&[], Vec::as_slice)
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.local.enabled(metadata) || Self::sinks().iter().any(|sink| sink.enabled(metadata))
+    }
+
+    fn log(&self, record: &Record) {
+        self.local.log(record);
+        for sink in Self::sinks() {
+            sink.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.local.flush();
+        for sink in Self::sinks() {
+            sink.flush();
+        }
+    }
+}
+
+pub fn init_logger(file_level: LevelFilter, console_level: LevelFilter, terminal: TerminalMode) {
     let mut path = env::current_exe().unwrap_or_default();
     path.set_file_name("relink_service.log");
 
@@ -11,21 +50,62 @@ pub fn init_logger() {
     let mut loggers: Vec<Box<dyn SharedLogger>> = Vec::new();
 
     loggers.push(TermLogger::new(
-        LevelFilter::Info,
+        console_level,
         Config::default(),
-        TerminalMode::Mixed,
+        terminal,
         ColorChoice::Auto,
     ));
 
     if let Some(f) = file {
         loggers.push(WriteLogger::new(
-            LevelFilter::Info,
+            file_level,
             Config::default(),
             f,
         ));
     } else {
         eprintln!("Failed to open log file for writing.");
     }
-    
-    let _ = CombinedLogger::init(loggers);
-}
\ No newline at end of file
+
+    let logger = Logger { local: CombinedLogger::new(loggers) };
+    if log::set_boxed_logger(Box::new(logger)).is_ok() {
+        log::set_max_level(file_level.max(console_level));
+    }
+}
+
+// Adds the syslog and journald sinks enabled in the config, at the log file's level.
+// Only the first call has an effect, changing them needs a restart.
+pub fn add_sinks(config: &AppConfig, level: LevelFilter) {
+    let mut sinks: Vec<Box<dyn Log>> = Vec::new();
+    if config.syslog.enabled {
+        match SyslogSink::connect(&config.syslog, level) {
+            Ok(sink) => sinks.push(Box::new(sink)),
+            Err(e) => log::warn!("Syslog output to {} disabled: {}", config.syslog.address, e),
+        }
+    }
+    if config.journald.enabled {
+        #[cfg(unix)]
+        match crate::journald::JournaldSink::connect(&config.journald, level) {
+            Ok(sink) => sinks.push(Box::new(sink)),
+            Err(e) => log::warn!("Journal output to {:?} disabled: {}", config.journald.socket, e),
+        }
+        #[cfg(not(unix))]
+        log::warn!("Journal output disabled, the journal is only available on Linux.");
+    }
+    let _ = SINKS.set(sinks);
+}
+
+// Structured fields attached with the key-value syntax, e.g. log::info!(adapter = name; "...")
+pub fn fields(record: &Record) -> Vec<(String, String)> {
+    struct Fields(Vec<(String, String)>);
+
+    impl<'kvs> VisitSource<'kvs> for Fields {
+        fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+            self.0.push((key.to_string(), value.to_string()));
+            Ok(())
+        }
+    }
+
+    let mut fields = Fields(Vec::new());
+    let _ = record.key_values().visit(&mut fields);
+    fields.0
+}
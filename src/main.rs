@@ -1,89 +1,646 @@
+// Builds for other platforms exist for development and tests. Without the service and
+// the adapter APIs, much of the monitor goes unused there.
+#![cfg_attr(not(windows), allow(dead_code, unreachable_code))]
+
+mod breaker;
+mod cli;
 mod config;
+#[cfg(windows)]
+mod console;
 mod device;
+mod format;
+mod history;
+mod hooks;
+mod ipc;
+mod journald;
+mod layers;
+mod lock;
 mod logger;
+mod metrics;
+mod migration;
+mod monitor;
+mod mqtt;
+mod notify;
+mod rpc;
+#[cfg(windows)]
+mod security;
+#[cfg(windows)]
 mod service;
+mod syslog;
+mod units;
+mod webhook;
+mod wizard;
+mod worker;
 
-use std::env;
+use clap::Parser;
+use log::LevelFilter;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use simplelog::TerminalMode;
+#[cfg(windows)]
 use std::io::stdin;
+use std::path::PathBuf;
+use std::process;
+#[cfg(windows)]
 use windows_service::{
     define_windows_service,
     service_dispatcher,
 };
 
+use crate::cli::{Cli, Command, ConfigCommand};
 use crate::config::AppConfig;
+#[cfg(windows)]
+use crate::console::{owns_console, ConsoleEvents, PowerEvents};
+use crate::breaker::CircuitBreaker;
+use crate::device::{
+    adapter_reports, check_and_fix_network, get_max_link_speed, manual_restart, AdapterReport, CheckOutcome,
+    CheckReport,
+};
+use crate::history::{RestartRecord, RestartStatus};
+use crate::migration::CURRENT_CONFIG_VERSION;
+#[cfg(windows)]
+use crate::monitor::{run_foreground, EventSource};
+use crate::monitor::MonitorStatus;
+use crate::rpc::ClientError;
+#[cfg(windows)]
 use crate::service::{my_service_main, install_service, uninstall_service};
-use crate::logger::init_logger;
+use crate::logger::{add_sinks, init_logger};
+use crate::units::LinkSpeed;
+use crate::wizard::run_init;
+use crate::worker::Trigger;
 
+#[cfg(windows)]
 define_windows_service!(ffi_service_main, my_service_main);
 
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Initialize config and logger early
-    AppConfig::init();
-    init_logger();
+    let cli = Cli::parse();
+    let global = &cli.global;
+
+    // Machine-readable output owns stdout
+    let terminal = if global.json { TerminalMode::Stderr } else { TerminalMode::Mixed };
+    let console_level = if global.quiet { LevelFilter::Error } else { global.log_level };
+    init_logger(global.log_level, console_level, terminal);
+
+    if let Some(path) = &global.config {
+        AppConfig::set_path_override(path.clone());
+    }
+    AppConfig::set_cli_overrides(global.overrides.clone());
 
-    let args: Vec<String> = env::args().collect();
+    // Config commands work on files that may not load, so they run before init
+    if let Some(Command::Config(command)) = &cli.command {
+        process::exit(run_config_command(command));
+    }
+
+    if let Err(e) = AppConfig::init() {
+        log::error!("{}", e);
+        process::exit(1);
+    }
     let config = AppConfig::global();
-    
-    // Command Line Interface
-    if args.len() > 1 {
-        match args[1].as_str() {
-            "install" => {
-                log::info!("Installing service '{}'...", config.service_display_name);
-                if let Err(e) = install_service() {
-                    log::error!("Failed to install service: {}", e);
+    add_sinks(&config, global.log_level);
+
+    let Some(command) = cli.command else {
+        run_dispatcher(&config.service_name);
+        return Ok(());
+    };
+
+    match command {
+        #[cfg(windows)]
+        Command::Install => {
+            // Config file creation if not exists, before the service starts reading it
+            if !AppConfig::get_path().exists() {
+                log::info!("Creating default config file...");
+                if let Err(e) = config.save() {
+                    log::error!("Failed to save default config: {}", e);
                 } else {
-                    log::info!("Success! Service installed.");
-                }
-                // Config file creation if not exists
-                if !AppConfig::get_path().exists() {
-                    log::info!("Creating default config file...");
-                    if let Err(e) = config.save() {
-                        log::error!("Failed to save default config: {}", e);
-                    } else {
-                        log::info!("Default config created at {:?}", AppConfig::get_path());
-                    }
+                    log::info!("Default config created at {:?}", AppConfig::get_path());
                 }
-                
-                log::info!("Press Enter to exit...");
-                let mut s = String::new();
-                stdin().read_line(&mut s)?;
             }
-            "uninstall" => {
-                log::info!("Uninstalling service '{}'...", config.service_display_name);
-                uninstall_service()?;
-                log::info!("Success! Service uninstalled.");
-                
-                log::info!("Press Enter to exit...");
-                let mut s = String::new();
-                stdin().read_line(&mut s)?;
+
+            log::info!("Installing service '{}'...", config.service_display_name);
+            let result = install_service();
+            match &result {
+                Ok(()) => log::info!("Success! Service installed with config {:?}", AppConfig::get_path()),
+                Err(e) => log::error!("Failed to install service: {}", e),
             }
-            _ => {
-                print_usage();
+            pause_if_own_console();
+            process::exit(if result.is_ok() { 0 } else { 1 });
+        }
+        #[cfg(windows)]
+        Command::Uninstall => {
+            log::info!("Uninstalling service '{}'...", config.service_display_name);
+            let result = uninstall_service();
+            match &result {
+                Ok(()) => log::info!("Success! Service uninstalled."),
+                Err(e) => log::error!("Failed to uninstall service: {}", e),
             }
+            pause_if_own_console();
+            process::exit(if result.is_ok() { 0 } else { 1 });
         }
-        return Ok(());
+        Command::Check { force, no_fix } => {
+            process::exit(check_command(force, !no_fix, global.json));
+        }
+        Command::Restart { adapter } => {
+            process::exit(restart_command(adapter));
+        }
+        #[cfg(windows)]
+        Command::Run => {
+            // Same loop as the service, with wake events from the power manager instead of the SCM
+            let sources: Vec<Box<dyn EventSource>> = vec![Box::new(ConsoleEvents), Box::new(PowerEvents::default())];
+            run_foreground(sources)?;
+        }
+        #[cfg(not(windows))]
+        Command::Install | Command::Uninstall | Command::Run => {
+            log::error!("Relink only monitors adapters on Windows.");
+            process::exit(1);
+        }
+        Command::ListAdapters => {
+            process::exit(list_adapters_command(&config.target_adapter_name, global.json));
+        }
+        Command::Status => {
+            process::exit(status_command(global.json));
+        }
+        Command::History { limit } => {
+            process::exit(history_command(limit, global.json));
+        }
+        Command::Control { action } => {
+            process::exit(control_command(&action));
+        }
+        Command::Config(_) => unreachable!("config commands exit before init"),
+    }
+    Ok(())
+}
+
+// Service Mode
+// Since init_logger is already called, logs will go to file (and std which service ignores/redirects)
+#[cfg(windows)]
+fn run_dispatcher(service_name: &str) {
+    if let Err(e) = service_dispatcher::start(service_name, ffi_service_main) {
+        log::error!("Failed to start service dispatcher: {}", e);
+        // If we are running in console but not as service, this error will show up.
+        println!("Hint: This program is a Windows Service. Run with 'install' to register it, or 'run' to monitor in the foreground.");
+        pause_if_own_console();
+    }
+}
+
+#[cfg(not(windows))]
+fn run_dispatcher(_service_name: &str) {
+    println!("Hint: Relink runs as a Windows Service. Run with --help for the commands available here.");
+}
+
+// Keeps a console window opened by double-clicking the executable from closing before
+// the output can be read. Scripts and terminals share their console and never wait.
+#[cfg(windows)]
+fn pause_if_own_console() {
+    if !owns_console() {
+        return;
+    }
+    log::info!("Press Enter to exit...");
+    let mut s = String::new();
+    let _ = stdin().read_line(&mut s);
+}
+
+fn run_config_command(command: &ConfigCommand) -> i32 {
+    match command {
+        ConfigCommand::Init(options) => run_init(options),
+        ConfigCommand::Validate { path } => validate_config(path.clone()),
+        ConfigCommand::Migrate { path, dry_run } => {
+            migrate_config(path.clone().unwrap_or_else(AppConfig::get_path), *dry_run)
+        }
+        ConfigCommand::Show { effective } => show_config(*effective),
+        ConfigCommand::Schema => {
+            println!("{}", serde_json::to_string_pretty(&AppConfig::json_schema()).expect("schema serializes"));
+            0
+        }
+        ConfigCommand::Convert { input, output } => convert_config(input.clone(), output.clone()),
     }
+}
 
-    // Service Mode
-    // Attempt to start the service dispatcher
-    // Since init_logger is already called, logs will go to file (and std which service ignores/redirects)
-    match service_dispatcher::start(&config.service_name, ffi_service_main) {
-        Ok(_) => {},
+// Without a path, checks the merged config including drop-ins and overrides
+fn validate_config(path: Option<PathBuf>) -> i32 {
+    let loaded = match &path {
+        Some(path) => AppConfig::load_from(path),
+        None => AppConfig::load(),
+    };
+    let path = path.unwrap_or_else(AppConfig::get_path);
+    let config = match loaded {
+        Ok(config) => config,
         Err(e) => {
-            log::error!("Failed to start service dispatcher: {}", e);
-            // If we are running in console but not as service, this error will show up.
-            // But since we are using the TeeLogger, it will also show in stdout.
-            println!("Hint: This program is a Windows Service. Run with 'install' to register it.");
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+
+    // The adapter's limits can only be checked on the machine it is plugged into
+    match get_max_link_speed(&config.target_adapter_name) {
+        Ok(Some(max_speed)) if config.link_speed_threshold_bps >= max_speed => {
+            eprintln!("Invalid config {}:", path.display());
+            eprintln!(
+                "  - link_speed_threshold_bps ({}) is not below the adapter's maximum speed ({}), a healthy link would count as degraded",
+                config.link_speed_threshold_bps, max_speed
+            );
+            return 1;
+        }
+        Ok(Some(_)) => {}
+        Ok(None) => eprintln!("Warning: adapter '{}' not found, skipped speed checks.", config.target_adapter_name),
+        Err(e) => eprintln!("Warning: could not query the adapter's maximum speed: {:?}", e),
+    }
+
+    println!("{} is valid.", path.display());
+    0
+}
+
+fn migrate_config(path: PathBuf, dry_run: bool) -> i32 {
+    match AppConfig::migrate_file(&path, dry_run) {
+        Ok(None) => {
+            println!("{} is already at version {}.", path.display(), CURRENT_CONFIG_VERSION);
+            0
+        }
+        Ok(Some((from_version, migrated))) => {
+            println!("{} version {} -> {}:", path.display(), from_version, CURRENT_CONFIG_VERSION);
+            println!("{}", migrated);
+            if !dry_run {
+                println!("Migrated. The previous file was kept as {}.bak", path.display());
+            }
+            0
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            1
         }
     }
-    Ok(())
 }
 
-fn print_usage() {
-    println!("Relink Network Monitor Service");
-    println!("Usage:");
-    println!("  relink install   - Install the service (Requires Admin)");
-    println!("  relink uninstall - Uninstall the service (Requires Admin)");
-    println!("  [No Arguments]   - Run as service (Called by SCM)");
+fn convert_config(input: PathBuf, output: PathBuf) -> i32 {
+    if output.exists() {
+        eprintln!("{} already exists, refusing to overwrite it.", output.display());
+        return 1;
+    }
+    let result = AppConfig::load_from(&input).and_then(|config| config.save_to(&output));
+    match result {
+        Ok(()) => {
+            println!("Converted {} to {}.", input.display(), output.display());
+            0
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+fn show_config(effective: bool) -> i32 {
+    if !effective {
+        let path = AppConfig::get_path();
+        let loaded = if path.exists() { AppConfig::load_from(&path) } else { Ok(AppConfig::default()) };
+        return match loaded.and_then(|config| redacted(config).to_string_for(&path)) {
+            Ok(content) => {
+                println!("# {}", path.display());
+                println!("{}", content);
+                0
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                1
+            }
+        };
+    }
+
+    let (config, provenance) = match AppConfig::load_effective() {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+    let value = serde_json::to_value(redacted(config)).expect("config serializes");
+    let mut lines = Vec::new();
+    flatten(&value, String::new(), &mut lines);
+
+    let width = lines.iter().map(|(key, value)| key.len() + value.len() + 3).max().unwrap_or(0);
+    for (key, value) in lines {
+        let source = provenance.get(&key).map(ToString::to_string).unwrap_or_else(|| "default".to_string());
+        let line = format!("{} = {}", key, value);
+        println!("{:<width$}  # {}", line, source, width = width);
+    }
+    0
+}
+
+const REDACTED: &str = "<redacted>";
+
+// Shown config often ends up pasted into issues, so credentials are masked
+fn redacted(mut config: AppConfig) -> AppConfig {
+    for webhook in &mut config.webhooks {
+        if webhook.secret.is_some() {
+            webhook.secret = Some(REDACTED.to_string());
+        }
+        // Headers usually carry authentication
+        for value in webhook.headers.values_mut() {
+            *value = REDACTED.to_string();
+        }
+    }
+    if config.mqtt.password.is_some() {
+        config.mqtt.password = Some(REDACTED.to_string());
+    }
+    config
+}
+
+fn flatten(value: &serde_json::Value, prefix: String, lines: &mut Vec<(String, String)>) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map {
+                let path = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+                flatten(value, path, lines);
+            }
+        }
+        _ => lines.push((prefix, value.to_string())),
+    }
+}
+
+// Asks the running service, or does the work in this process if there is none to ask
+fn call_service_or<T: DeserializeOwned>(
+    method: &str,
+    params: Value,
+    local: impl FnOnce() -> Result<T, String>,
+) -> Result<T, String> {
+    match rpc::call(method, params) {
+        Err(e) if e.is_unavailable() => {
+            log::debug!("{}, running '{}' in this process.", e, method);
+            local()
+        }
+        result => result.map_err(|e| e.to_string()),
+    }
+}
+
+// Runs the check in the service, so it shares its pause state and circuit breaker. Without
+// a service it runs in this process, where the breaker starts empty and only limits
+// restarts within this run.
+fn check_command(force: bool, fix_allowed: bool, json: bool) -> i32 {
+    let params = json!({ "force": force, "no_fix": !fix_allowed });
+    let report: CheckReport = match call_service_or("check", params, || {
+        let config = AppConfig::global();
+        let mut breaker = CircuitBreaker::new(config.breaker_max_restarts, config.breaker_window_secs.duration());
+        let trigger = if force { Trigger::Manual } else { Trigger::Periodic };
+        Ok(check_and_fix_network(trigger, fix_allowed, &mut breaker))
+    }) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report).expect("report serializes"));
+    } else {
+        let speed = report.speed.map(|speed| speed.to_string()).unwrap_or_else(|| "unknown".to_string());
+        let summary = match &report.outcome {
+            CheckOutcome::Healthy => "healthy".to_string(),
+            CheckOutcome::Fixed => "degraded, adapter restarted".to_string(),
+            CheckOutcome::NotFixed { reason } => format!("degraded, not fixed: {}", reason),
+            CheckOutcome::AdapterNotFound => "adapter not found".to_string(),
+            CheckOutcome::Error { message } => format!("error: {}", message),
+        };
+        println!("{}: {} (threshold {}), {}", report.adapter, speed, report.threshold, summary);
+    }
+    report.outcome.exit_code()
+}
+
+// Restarts the configured adapter (or the one `adapter` matches) the way the service does,
+// waiting for a restart the service may have in progress
+fn restart_command(adapter: Option<String>) -> i32 {
+    let params = json!({ "adapter": adapter });
+    let record: Option<RestartRecord> = match call_service_or("restart", params, || {
+        let record = manual_restart(adapter);
+        webhook::flush();
+        Ok(record)
+    }) {
+        Ok(record) => record,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+    let Some(record) = record else {
+        eprintln!("The service is still restarting the adapter, try again later.");
+        return 6;
+    };
+
+    let speed_after = record.speed_after.map(|speed| speed.to_string()).unwrap_or_else(|| "unknown".to_string());
+    match record.status {
+        RestartStatus::Recovered => {
+            println!("Restarted '{}', link is up at {}.", record.adapter, speed_after);
+            0
+        }
+        RestartStatus::StillDegraded => {
+            println!("Restarted '{}', but the link is still degraded ({}).", record.adapter, speed_after);
+            4
+        }
+        RestartStatus::DeviceNotFound => {
+            eprintln!("Device '{}' not found.", record.adapter);
+            5
+        }
+        RestartStatus::Failed => {
+            eprintln!("{}", record.error.unwrap_or_default());
+            1
+        }
+        RestartStatus::Vetoed => {
+            eprintln!("Restart vetoed: {}", record.error.unwrap_or_default());
+            7
+        }
+    }
+}
+
+fn list_adapters_command(target_adapter_name: &str, json: bool) -> i32 {
+    let reports: Vec<AdapterReport> = match call_service_or("adapters", Value::Null, || {
+        adapter_reports(target_adapter_name).map_err(|e| format!("Failed to list network adapters: {:?}", e))
+    }) {
+        Ok(reports) => reports,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&reports).expect("adapters serialize"));
+        return 0;
+    }
+
+    for report in &reports {
+        let adapter = &report.adapter;
+        let marker = if report.matched_by.is_some() { "*" } else { " " };
+        println!("{} [{}] {}", marker, adapter.index, adapter.friendly_name);
+        println!("      Description: {}", adapter.description);
+        println!("      LUID:        {:#018x}", adapter.luid);
+        println!("      MAC:         {}", if adapter.mac.is_empty() { "-" } else { &adapter.mac });
+        println!("      Status:      {}", adapter.status);
+        println!(
+            "      Speed:       {} rx / {} tx (max {})",
+            adapter.receive_speed,
+            adapter.transmit_speed,
+            adapter.max_speed.map(|speed| speed.to_string()).unwrap_or_else(|| "unknown".to_string())
+        );
+        println!("      Duplex:      {}", adapter.duplex);
+        println!("      Driver:      {}", adapter.driver.as_deref().unwrap_or("-"));
+        if let Some(field) = &report.matched_by {
+            println!("      Matched by:  {} = \"{}\"", field, target_adapter_name);
+        }
+    }
+    if reports.iter().all(|report| report.matched_by.is_none()) {
+        println!();
+        println!("No adapter matches target_adapter_name = \"{}\".", target_adapter_name);
+    }
+    0
+}
+
+fn status_command(json: bool) -> i32 {
+    let status: MonitorStatus = match rpc::call("status", Value::Null) {
+        Ok(status) => status,
+        Err(e) => {
+            eprintln!("{}", e);
+            return if matches!(e, ClientError::NotRunning(_)) { 3 } else { 1 };
+        }
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&status).expect("status serializes"));
+        return 0;
+    }
+
+    let time = |time: Option<u64>| time.map(history::format_time).unwrap_or_else(|| "never".to_string());
+    let speed = |speed: Option<LinkSpeed>| speed.map(|speed| speed.to_string()).unwrap_or_else(|| "unknown".to_string());
+    let breaker = &status.breaker;
+    println!("Service:       {} (pid {}, version {})", status.service_name, status.pid, status.version);
+    println!("State:         {}", if status.paused { "paused" } else { "running" });
+    println!("Config:        {} (version {})", status.config_path.display(), status.config_version);
+    println!("Last wake:     {}", time(status.last_wake));
+    println!(
+        "Breaker:       {}, {} of {} restarts left per {}",
+        if breaker.open { "open" } else { "closed" },
+        breaker.remaining,
+        breaker.max_restarts,
+        breaker.window
+    );
+    for adapter in &status.adapters {
+        println!();
+        println!("Adapter:       {} (threshold {})", adapter.adapter, adapter.threshold);
+        println!("  State:        {}", adapter.state.name());
+        match &adapter.last_check {
+            Some(check) => println!(
+                "  Last check:   {}, {} ({})",
+                history::format_time(check.time),
+                speed(check.report.speed),
+                check.trigger.name()
+            ),
+            None => println!("  Last check:   never"),
+        }
+        match &adapter.last_restart {
+            Some(record) => println!(
+                "  Last restart: {}, {} {} -> {}, {}",
+                history::format_time(record.time),
+                record.cause.name(),
+                speed(record.speed_before),
+                speed(record.speed_after),
+                record.status.name()
+            ),
+            None => println!("  Last restart: never"),
+        }
+    }
+    0
+}
+
+// The history file is shared, so it can be read without the service
+fn history_command(limit: usize, json: bool) -> i32 {
+    let records: Vec<RestartRecord> = match call_service_or("history", json!({ "limit": limit }), || {
+        history::load()
+            .map(|records| records[records.len().saturating_sub(limit)..].to_vec())
+            .map_err(|e| format!("Failed to read {}: {}", history::history_path().display(), e))
+    }) {
+        Ok(records) => records,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&records).expect("history serializes"));
+        return 0;
+    }
+    if records.is_empty() {
+        println!("No restarts recorded.");
+    }
+    for record in &records {
+        let speed = |speed: Option<LinkSpeed>| speed.map(|speed| speed.to_string()).unwrap_or_else(|| "unknown".to_string());
+        println!(
+            "{}  {:<9}  {:<15}  {} -> {}  {}",
+            history::format_time(record.time),
+            record.cause.name(),
+            record.status.name(),
+            speed(record.speed_before),
+            speed(record.speed_after),
+            record.adapter
+        );
+    }
+    0
+}
+
+// Talks to the service only, there is nothing to control without one
+fn control_command(action: &str) -> i32 {
+    let method = action.replace('-', "_");
+    match rpc::call::<Value>(&method, Value::Null) {
+        Ok(result) => {
+            if !result.is_null() {
+                println!("{}", serde_json::to_string_pretty(&result).expect("result serializes"));
+            }
+            log::info!("Sent '{}' to service '{}'.", action, AppConfig::global().service_name);
+            0
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mqtt::MqttConfig;
+    use crate::webhook::WebhookConfig;
+
+    #[test]
+    fn redacts_webhook_credentials() {
+        let config = AppConfig {
+            webhooks: vec![
+                WebhookConfig {
+                    url: "https://example.com/hook".to_string(),
+                    headers: [("Authorization".to_string(), "Bearer token".to_string())].into(),
+                    secret: Some("key".to_string()),
+                    events: Vec::new(),
+                },
+                WebhookConfig::default(),
+            ],
+            ..AppConfig::default()
+        };
+        let config = redacted(config);
+        assert_eq!(config.webhooks[0].url, "https://example.com/hook");
+        assert_eq!(config.webhooks[0].headers["Authorization"], REDACTED);
+        assert_eq!(config.webhooks[0].secret.as_deref(), Some(REDACTED));
+        assert_eq!(config.webhooks[1].secret, None);
+        assert_eq!(config.mqtt.password, None);
+    }
+
+    #[test]
+    fn redacts_mqtt_password() {
+        let mqtt = MqttConfig {
+            username: Some("relink".to_string()),
+            password: Some("hunter2".to_string()),
+            ..MqttConfig::default()
+        };
+        let config = redacted(AppConfig { mqtt, ..AppConfig::default() });
+        assert_eq!(config.mqtt.username.as_deref(), Some("relink"));
+        assert_eq!(config.mqtt.password.as_deref(), Some(REDACTED));
+    }
 }
@@ -0,0 +1,304 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::device::{list_adapters, CheckOutcome};
+use crate::ipc;
+use crate::monitor::MonitorHandle;
+use crate::worker::{AdapterState, Trigger};
+
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+// Upper bounds in seconds, wake-to-healthy usually includes wait_after_wake_secs
+const WAKE_TO_HEALTHY_BUCKETS: [f64; 10] = [5.0, 10.0, 15.0, 20.0, 30.0, 45.0, 60.0, 90.0, 120.0, 300.0];
+
+// Counters since this process started, labelled by trigger or restart strategy
+struct Counters {
+    checks: BTreeMap<String, u64>,
+    degradations: BTreeMap<String, u64>,
+    restarts_attempted: BTreeMap<String, u64>,
+    restarts_succeeded: BTreeMap<String, u64>,
+    wake_to_healthy_buckets: [u64; WAKE_TO_HEALTHY_BUCKETS.len()],
+    wake_to_healthy_count: u64,
+    wake_to_healthy_sum: f64,
+}
+
+static COUNTERS: Mutex<Counters> = Mutex::new(Counters {
+    checks: BTreeMap::new(),
+    degradations: BTreeMap::new(),
+    restarts_attempted: BTreeMap::new(),
+    restarts_succeeded: BTreeMap::new(),
+    wake_to_healthy_buckets: [0; WAKE_TO_HEALTHY_BUCKETS.len()],
+    wake_to_healthy_count: 0,
+    wake_to_healthy_sum: 0.0,
+});
+
+fn counters() -> std::sync::MutexGuard<'static, Counters> {
+    COUNTERS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn trigger_label(trigger: Trigger) -> String {
+    trigger.name().replace(' ', "_")
+}
+
+// A link found degraded, whether or not a restart fixed it, counts once until a check
+// finds it in another state
+pub fn record_check(trigger: Trigger, outcome: &CheckOutcome, previous: AdapterState) {
+    let mut counters = counters();
+    *counters.checks.entry(trigger_label(trigger)).or_default() += 1;
+    if matches!(outcome, CheckOutcome::Fixed | CheckOutcome::NotFixed { .. }) && previous != AdapterState::Degraded {
+        *counters.degradations.entry(trigger_label(trigger)).or_default() += 1;
+    }
+}
+
+pub fn record_restart(strategy: &str, succeeded: bool) {
+    let mut counters = counters();
+    *counters.restarts_attempted.entry(strategy.to_string()).or_default() += 1;
+    if succeeded {
+        *counters.restarts_succeeded.entry(strategy.to_string()).or_default() += 1;
+    }
+}
+
+pub fn record_wake_to_healthy(elapsed: Duration) {
+    let secs = elapsed.as_secs_f64();
+    let mut counters = counters();
+    for (bucket, bound) in counters.wake_to_healthy_buckets.iter_mut().zip(WAKE_TO_HEALTHY_BUCKETS) {
+        if secs <= bound {
+            *bucket += 1;
+        }
+    }
+    counters.wake_to_healthy_count += 1;
+    counters.wake_to_healthy_sum += secs;
+}
+
+// Serves GET /metrics in the Prometheus text format until dropped
+pub struct MetricsServer {
+    address: SocketAddr,
+    stop: Arc<AtomicBool>,
+}
+
+impl MetricsServer {
+    pub fn start(address: SocketAddr, handle: MonitorHandle) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let address = listener.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_in_thread = stop.clone();
+
+        // Scrapes are rare, so they are answered one at a time
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if stop_in_thread.load(Ordering::SeqCst) {
+                    break;
+                }
+                match stream {
+                    Ok(stream) => {
+                        if let Err(e) = serve(stream, &handle) {
+                            log::debug!("Metrics request failed: {}", e);
+                        }
+                    }
+                    Err(e) => log::warn!("Metrics connection failed: {}", e),
+                }
+            }
+        });
+
+        log::info!("Metrics available at http://{}/metrics", address);
+        Ok(Self { address, stop })
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        ipc::stop_accept_loop(&self.stop, || TcpStream::connect_timeout(&self.address, READ_TIMEOUT));
+    }
+}
+
+fn serve(stream: TcpStream, handle: &MonitorHandle) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // The headers are not needed, but must be read before answering
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && !header.trim().is_empty() {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+    let path = path.split('?').next().unwrap_or_default();
+    let (status, body) = match (method, path) {
+        ("GET", "/metrics") => ("200 OK", render(handle)),
+        (_, "/metrics") => ("405 Method Not Allowed", "Only GET is supported.\n".to_string()),
+        _ => ("404 Not Found", "Metrics are served at /metrics.\n".to_string()),
+    };
+
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        CONTENT_TYPE,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+fn render(handle: &MonitorHandle) -> String {
+    let mut out = String::new();
+    let status = handle.status();
+
+    family(&mut out, "relink_link_speed_bps", "gauge", "Current link speed of each adapter, by direction.");
+    match list_adapters() {
+        Ok(adapters) => {
+            for adapter in adapters.iter().filter(|adapter| adapter.is_physical()) {
+                let name = escape(&adapter.friendly_name);
+                for (direction, speed) in [("rx", adapter.receive_speed), ("tx", adapter.transmit_speed)] {
+                    let _ = writeln!(
+                        out,
+                        "relink_link_speed_bps{{adapter=\"{}\",direction=\"{}\"}} {}",
+                        name,
+                        direction,
+                        speed.bps()
+                    );
+                }
+            }
+        }
+        Err(e) => log::warn!("Failed to list adapters for metrics: {:?}", e),
+    }
+
+    family(&mut out, "relink_paused", "gauge", "1 while automatic actions are paused.");
+    let _ = writeln!(out, "relink_paused {}", u8::from(status.paused));
+    family(&mut out, "relink_circuit_breaker_open", "gauge", "1 while the circuit breaker refuses restarts.");
+    let _ = writeln!(out, "relink_circuit_breaker_open {}", u8::from(status.breaker.open));
+    family(
+        &mut out,
+        "relink_circuit_breaker_remaining_restarts",
+        "gauge",
+        "Restarts left before the circuit breaker opens.",
+    );
+    let _ = writeln!(out, "relink_circuit_breaker_remaining_restarts {}", status.breaker.remaining);
+
+    let counters = counters();
+    labelled(&mut out, "relink_checks_total", "Link checks performed, by trigger.", "trigger", &counters.checks);
+    labelled(
+        &mut out,
+        "relink_degradations_total",
+        "Times a check found the link newly degraded, by trigger.",
+        "trigger",
+        &counters.degradations,
+    );
+    labelled(
+        &mut out,
+        "relink_restarts_attempted_total",
+        "Adapter restarts attempted, by strategy.",
+        "strategy",
+        &counters.restarts_attempted,
+    );
+    labelled(
+        &mut out,
+        "relink_restarts_succeeded_total",
+        "Adapter restarts after which the link recovered, by strategy.",
+        "strategy",
+        &counters.restarts_succeeded,
+    );
+
+    family(
+        &mut out,
+        "relink_wake_to_healthy_seconds",
+        "histogram",
+        "Time from a system wake to a healthy link, including any restart.",
+    );
+    for (count, bound) in counters.wake_to_healthy_buckets.iter().zip(WAKE_TO_HEALTHY_BUCKETS) {
+        let _ = writeln!(out, "relink_wake_to_healthy_seconds_bucket{{le=\"{}\"}} {}", bound, count);
+    }
+    let count = counters.wake_to_healthy_count;
+    let _ = writeln!(out, "relink_wake_to_healthy_seconds_bucket{{le=\"+Inf\"}} {}", count);
+    let _ = writeln!(out, "relink_wake_to_healthy_seconds_sum {}", counters.wake_to_healthy_sum);
+    let _ = writeln!(out, "relink_wake_to_healthy_seconds_count {}", count);
+    out
+}
+
+fn family(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn labelled(out: &mut String, name: &str, help: &str, label: &str, values: &BTreeMap<String, u64>) {
+    family(out, name, "counter", help);
+    for (value, count) in values {
+        let _ = writeln!(out, "{}{{{}=\"{}\"}} {}", name, label, escape(value), count);
+    }
+}
+
+// Label values escape backslash, double quote and newline
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AppConfig;
+    use crate::monitor::Monitor;
+    use std::io::Read;
+
+    fn degradations(trigger: Trigger) -> u64 {
+        counters().degradations.get(&trigger_label(trigger)).copied().unwrap_or_default()
+    }
+
+    fn not_fixed() -> CheckOutcome {
+        CheckOutcome::NotFixed { reason: "test".to_string() }
+    }
+
+    #[test]
+    fn degradations_count_transitions() {
+        // Other tests only record link change checks that fail, never degraded ones
+        let trigger = Trigger::LinkChange;
+        let before = degradations(trigger);
+        record_check(trigger, &not_fixed(), AdapterState::Healthy);
+        record_check(trigger, &not_fixed(), AdapterState::Degraded);
+        record_check(trigger, &CheckOutcome::Fixed, AdapterState::Degraded);
+        record_check(trigger, &CheckOutcome::Healthy, AdapterState::Healthy);
+        record_check(trigger, &CheckOutcome::Fixed, AdapterState::Healthy);
+        assert_eq!(degradations(trigger), before + 2);
+    }
+
+    fn scrape(address: SocketAddr, request_line: &str) -> String {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(stream, "{}\r\nHost: localhost\r\n\r\n", request_line).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn serves_metrics() {
+        AppConfig::use_defaults();
+        let monitor = Monitor::new();
+        let server = MetricsServer::start("127.0.0.1:0".parse().unwrap(), monitor.handle()).unwrap();
+
+        let response = scrape(server.address, "GET /metrics?format=text HTTP/1.1");
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(head.contains(&format!("Content-Type: {}", CONTENT_TYPE)));
+        assert!(head.contains(&format!("Content-Length: {}", body.len())));
+        assert!(body.contains("# TYPE relink_paused gauge\nrelink_paused 0\n"));
+        assert!(body.contains("relink_circuit_breaker_open 0\n"));
+        assert!(body.contains("# TYPE relink_checks_total counter\n"));
+        assert!(body.contains("relink_wake_to_healthy_seconds_bucket{le=\"+Inf\"}"));
+
+        assert!(scrape(server.address, "POST /metrics HTTP/1.1").starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+        assert!(scrape(server.address, "GET / HTTP/1.1").starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+
+    #[test]
+    fn escapes_label_values() {
+        assert_eq!(escape("a\\b\"c\nd"), "a\\\\b\\\"c\\nd");
+    }
+}
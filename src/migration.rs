@@ -0,0 +1,54 @@
+use serde_json::{Map, Value};
+
+pub const CURRENT_CONFIG_VERSION: u32 = 2;
+
+type Migration = fn(&mut Map<String, Value>);
+
+// MIGRATIONS[n] upgrades a version n + 1 config to version n + 2
+const MIGRATIONS: &[Migration] = &[v1_to_v2];
+
+// Files written before the version field existed are version 1
+pub fn config_version(config: &Map<String, Value>) -> Result<u32, String> {
+    match config.get("version") {
+        None => Ok(1),
+        Some(value) => value
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .filter(|&v| v >= 1)
+            .ok_or_else(|| format!("version must be a positive integer, got {}", value)),
+    }
+}
+
+// Upgrades the config in place, one version at a time. Returns the version it started from.
+pub fn migrate(config: &mut Value) -> Result<u32, String> {
+    let Some(config) = config.as_object_mut() else {
+        return Err("config must be a JSON object".to_string());
+    };
+
+    let from = config_version(config)?;
+    if from > CURRENT_CONFIG_VERSION {
+        return Err(format!(
+            "config version {} is newer than the supported version {}",
+            from, CURRENT_CONFIG_VERSION
+        ));
+    }
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(from as usize - 1) {
+        migration(config);
+        config.insert("version".to_string(), Value::from(index as u32 + 2));
+    }
+    Ok(from)
+}
+
+// Version 1 was the flat six-field layout. Version 2 adds the explicit version
+// and the circuit breaker limits.
+//
+// Migrations are frozen: they write the values version 2 shipped with, not the current
+// AppConfig defaults, so an old file upgrades the same way whenever it is migrated. A
+// later change of default needs its own migration.
+fn v1_to_v2(config: &mut Map<String, Value>) {
+    const BREAKER_MAX_RESTARTS: u32 = 3;
+    const BREAKER_WINDOW_SECS: u64 = 3600;
+    config.entry("breaker_max_restarts").or_insert(Value::from(BREAKER_MAX_RESTARTS));
+    config.entry("breaker_window_secs").or_insert(Value::from(BREAKER_WINDOW_SECS));
+}
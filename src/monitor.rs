@@ -0,0 +1,452 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::path::PathBuf;
+use std::process;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::config::{AppConfig, ConfigError, ConfigWatcher};
+use crate::device::{adapter_description, manual_restart, max_restart_duration, CheckReport, LinkWatcher};
+use crate::ipc::endpoint_name;
+use crate::metrics::MetricsServer;
+use crate::mqtt::MqttPublisher;
+use crate::webhook;
+use crate::rpc::RpcServer;
+use crate::history::{self, RestartRecord};
+use crate::units::{Interval, LinkSpeed};
+use crate::worker::{spawn_worker, AdapterState, FixPolicy, Job, LastCheck, RecoveryQueue, Trigger};
+
+const STARTUP_CHECK_DELAY: Duration = Duration::from_secs(5);
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+const STOP_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+// Everything that can drive a running monitor, whichever source it came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MonitorEvent {
+    Wake,
+    Check,
+    Pause,
+    Resume,
+    ResetBreaker,
+    ReloadConfig,
+    Stop,
+}
+
+// Delivers events to a monitor, e.g. the SCM control handler, the console or a test script.
+// Sources stop delivering when dropped.
+pub trait EventSource {
+    fn start(&mut self, handle: MonitorHandle) -> Result<(), Box<dyn Error>>;
+}
+
+type PauseObserver = Box<dyn Fn(bool) + Send + Sync>;
+
+// Manual restarts run on the RPC or MQTT thread that asked for them, outside the worker.
+// Stopping refuses new ones and waits for these, a restart cut short leaves the adapter disabled.
+#[derive(Default)]
+struct Restarts {
+    running: usize,
+    closed: bool,
+}
+
+type SharedRestarts = Arc<(Mutex<Restarts>, Condvar)>;
+
+// Counts a manual restart as running until dropped
+pub struct RestartGuard(SharedRestarts);
+
+impl Drop for RestartGuard {
+    fn drop(&mut self) {
+        let (restarts, changed) = &*self.0;
+        restarts.lock().unwrap_or_else(|e| e.into_inner()).running -= 1;
+        changed.notify_all();
+    }
+}
+
+#[derive(Clone)]
+pub struct MonitorHandle {
+    queue: RecoveryQueue,
+    stop: Sender<()>,
+    pause_observer: Arc<OnceLock<PauseObserver>>,
+    restarts: SharedRestarts,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MonitorStatus {
+    pub pid: u32,
+    pub version: String,
+    pub service_name: String,
+    pub config_path: PathBuf,
+    pub config_version: u32,
+    pub paused: bool,
+    // Seconds since the Unix epoch
+    pub last_wake: Option<u64>,
+    pub breaker: BreakerStatus,
+    pub adapters: Vec<AdapterStatus>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BreakerStatus {
+    pub open: bool,
+    // Restarts left in the current window
+    pub remaining: u32,
+    pub max_restarts: u32,
+    pub window: Interval,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdapterStatus {
+    // As configured, the adapter it selects is in the reports
+    pub adapter: String,
+    pub threshold: LinkSpeed,
+    pub state: AdapterState,
+    pub last_check: Option<LastCheck>,
+    pub last_restart: Option<RestartRecord>,
+}
+
+impl MonitorHandle {
+    // Only a config reload can fail, the current config then stays active
+    pub fn send(&self, event: MonitorEvent) -> Result<(), ConfigError> {
+        match event {
+            MonitorEvent::Wake => {
+                log::info!("System wake detected.");
+                self.queue.submit(Trigger::Wake);
+            }
+            MonitorEvent::Check => {
+                log::info!("Manual check requested.");
+                self.queue.submit(Trigger::Manual);
+            }
+            MonitorEvent::Pause => {
+                log::info!("Paused. Monitoring continues, automatic actions are suspended.");
+                self.set_paused(true);
+            }
+            MonitorEvent::Resume => {
+                log::info!("Resumed.");
+                self.set_paused(false);
+            }
+            MonitorEvent::ResetBreaker => self.queue.reset_breaker(),
+            MonitorEvent::ReloadConfig => AppConfig::reload()?,
+            MonitorEvent::Stop => {
+                let _ = self.stop.send(());
+            }
+        }
+        Ok(())
+    }
+
+    // Queues a check and returns a receiver for its report
+    pub fn check(&self, force: bool, fix: bool) -> Receiver<CheckReport> {
+        self.queue.request(check_job(force, fix))
+    }
+
+    pub fn status(&self) -> MonitorStatus {
+        let config = AppConfig::global();
+        let worker = self.queue.status();
+        MonitorStatus {
+            pid: process::id(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            service_name: config.service_name.clone(),
+            config_path: AppConfig::get_path(),
+            config_version: config.version,
+            paused: self.queue.is_paused(),
+            last_wake: worker.last_wake,
+            breaker: BreakerStatus {
+                open: worker.breaker_remaining == 0,
+                remaining: worker.breaker_remaining,
+                max_restarts: config.breaker_max_restarts,
+                window: config.breaker_window_secs,
+            },
+            adapters: vec![AdapterStatus {
+                adapter: config.target_adapter_name.clone(),
+                threshold: config.link_speed_threshold_bps,
+                state: worker.state,
+                last_check: worker.last_check,
+                last_restart: worker.last_restart,
+            }],
+        }
+    }
+
+    // Returns None once the monitor is stopping
+    pub fn begin_restart(&self) -> Option<RestartGuard> {
+        let mut restarts = self.restarts.0.lock().unwrap_or_else(|e| e.into_inner());
+        if restarts.closed {
+            return None;
+        }
+        restarts.running += 1;
+        Some(RestartGuard(self.restarts.clone()))
+    }
+
+    // Like manual_restart, and shown as the last restart if it was of the monitored adapter.
+    // Returns None without restarting once the monitor is stopping.
+    pub fn restart(&self, adapter: Option<String>) -> Option<RestartRecord> {
+        let Some(guard) = self.begin_restart() else {
+            log::warn!("Monitoring is stopping, not restarting the adapter.");
+            return None;
+        };
+        self.restart_counted(guard, adapter)
+    }
+
+    // For a restart counted before handing it to another thread, so a stop in between
+    // still waits for it
+    pub fn restart_counted(&self, _guard: RestartGuard, adapter: Option<String>) -> Option<RestartRecord> {
+        let record = manual_restart(adapter)?;
+        if is_monitored(&record) {
+            self.queue.set_last_restart(record.clone());
+        }
+        Some(record)
+    }
+
+    fn set_paused(&self, paused: bool) {
+        self.queue.set_paused(paused);
+        if let Some(observer) = self.pause_observer.get() {
+            observer(paused);
+        }
+    }
+}
+
+// Like a local check: a forced check may restart a degraded adapter, even while paused
+// like the SCM check control, unless `fix` is off
+fn check_job(force: bool, fix: bool) -> Job {
+    let trigger = if force { Trigger::Manual } else { Trigger::Periodic };
+    let policy = match (fix, force) {
+        (false, _) => FixPolicy::Never,
+        (true, true) => FixPolicy::Always,
+        (true, false) => FixPolicy::UnlessPaused,
+    };
+    Job::new(trigger, policy)
+}
+
+// Records name the full device description when it could be resolved, otherwise the
+// configured matcher
+fn is_monitored(record: &RestartRecord) -> bool {
+    let matcher = &AppConfig::global().target_adapter_name;
+    record.adapter.contains(matcher.as_str())
+        || adapter_description(matcher).ok().flatten().as_ref() == Some(&record.adapter)
+}
+
+// Restarts from before this process started are only in the history
+fn last_restart_from_history() -> Option<RestartRecord> {
+    match history::load() {
+        Ok(records) => records.into_iter().rev().find(is_monitored),
+        Err(e) => {
+            log::warn!("Failed to read restart history: {}", e);
+            None
+        }
+    }
+}
+
+pub struct Monitor {
+    handle: MonitorHandle,
+    stop_rx: Receiver<()>,
+    worker: JoinHandle<()>,
+}
+
+impl Monitor {
+    pub fn new() -> Self {
+        let (stop, stop_rx) = mpsc::channel();
+        let (queue, worker) = spawn_worker();
+        if let Some(record) = last_restart_from_history() {
+            queue.set_last_restart(record);
+        }
+        Self {
+            handle: MonitorHandle {
+                queue,
+                stop,
+                pause_observer: Arc::new(OnceLock::new()),
+                restarts: SharedRestarts::default(),
+            },
+            stop_rx,
+            worker,
+        }
+    }
+
+    pub fn handle(&self) -> MonitorHandle {
+        self.handle.clone()
+    }
+
+    // Called on every pause or resume, whichever source requested it
+    pub fn on_pause_change(&self, observer: impl Fn(bool) + Send + Sync + 'static) {
+        let _ = self.handle.pause_observer.set(Box::new(observer));
+    }
+
+    // Opt-in, a failure to listen doesn't stop monitoring
+    fn start_metrics(handle: MonitorHandle) -> Option<MetricsServer> {
+        let config = AppConfig::global();
+        if !config.metrics.enabled {
+            return None;
+        }
+        let address = config.metrics.address()?;
+        match MetricsServer::start(address, handle) {
+            Ok(server) => Some(server),
+            Err(e) => {
+                log::warn!("Failed to serve metrics on {}: {}", address, e);
+                None
+            }
+        }
+    }
+
+    fn start_mqtt(handle: MonitorHandle) -> Option<MqttPublisher> {
+        let config = AppConfig::global();
+        if !config.mqtt.enabled {
+            return None;
+        }
+        match MqttPublisher::start(&config.mqtt, handle) {
+            Ok(publisher) => Some(publisher),
+            Err(e) => {
+                log::warn!("Failed to set up MQTT: {}", e);
+                None
+            }
+        }
+    }
+
+    // Runs periodic checks and watches links and the config file until a Stop event, then
+    // waits for the recovery in flight and any manual restart. `stopping` is called with an increasing checkpoint and
+    // the expected remaining time while waiting.
+    pub fn run(self, mut stopping: impl FnMut(u32, Duration)) {
+        let queue = &self.handle.queue;
+        let link_watcher = match LinkWatcher::start(queue.clone()) {
+            Ok(watcher) => Some(watcher),
+            Err(e) => {
+                log::warn!("Failed to subscribe to link change notifications: {:?}", e);
+                None
+            }
+        };
+
+        let config_watcher = ConfigWatcher::start();
+
+        let endpoint = endpoint_name(&AppConfig::global().service_name);
+        let rpc_server = match RpcServer::start(&endpoint, self.handle.clone()) {
+            Ok(server) => Some(server),
+            Err(e) => {
+                log::warn!("Failed to open the control API at {}: {}", endpoint, e);
+                None
+            }
+        };
+
+        let metrics_server = Self::start_metrics(self.handle.clone());
+        webhook::start();
+        let mqtt_publisher = Self::start_mqtt(self.handle.clone());
+
+        // Main loop, wakes up as soon as Stop arrives
+        let mut next_wait = STARTUP_CHECK_DELAY;
+        while let Err(RecvTimeoutError::Timeout) = self.stop_rx.recv_timeout(next_wait) {
+            queue.submit(Trigger::Periodic);
+            next_wait = CHECK_INTERVAL;
+        }
+
+        log::info!("Stop requested, waiting for in-flight recovery to finish...");
+        self.handle.restarts.0.lock().unwrap_or_else(|e| e.into_inner()).closed = true;
+        drop(mqtt_publisher);
+        drop(metrics_server);
+        drop(rpc_server);
+        drop(config_watcher);
+        drop(link_watcher);
+        queue.stop();
+
+        let wait_hint = max_restart_duration(&AppConfig::global());
+        let mut checkpoint = 1;
+        loop {
+            stopping(checkpoint, wait_hint);
+            if self.worker.is_finished() {
+                break;
+            }
+            checkpoint += 1;
+            thread::sleep(STOP_POLL_INTERVAL);
+        }
+        let _ = self.worker.join();
+
+        let (restarts, changed) = &*self.handle.restarts;
+        let mut restarts = restarts.lock().unwrap_or_else(|e| e.into_inner());
+        if restarts.running > 0 {
+            log::info!("Waiting for {} manual restart(s) to finish...", restarts.running);
+        }
+        while restarts.running > 0 {
+            checkpoint += 1;
+            stopping(checkpoint, wait_hint);
+            restarts = changed.wait_timeout(restarts, STOP_POLL_INTERVAL).unwrap_or_else(|e| e.into_inner()).0;
+        }
+    }
+}
+
+// Runs the monitor in this process until one of the sources sends Stop
+pub fn run_foreground(mut sources: Vec<Box<dyn EventSource>>) -> Result<(), Box<dyn Error>> {
+    let monitor = Monitor::new();
+    for source in &mut sources {
+        source.start(monitor.handle())?;
+    }
+
+    log::info!("Monitoring started in the foreground.");
+    monitor.run(|_, _| {});
+    drop(sources);
+    log::info!("Monitoring stopped.");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    // Sends Check, waits for its result to show up in the status, then sends Stop
+    struct Script {
+        seen: Arc<Mutex<Option<LastCheck>>>,
+    }
+
+    impl EventSource for Script {
+        fn start(&mut self, handle: MonitorHandle) -> Result<(), Box<dyn Error>> {
+            let seen = self.seen.clone();
+            thread::spawn(move || {
+                handle.send(MonitorEvent::Check).unwrap();
+                let deadline = Instant::now() + Duration::from_secs(10);
+                while Instant::now() < deadline {
+                    if let Some(check) = handle.status().adapters[0].last_check.clone() {
+                        *seen.lock().unwrap() = Some(check);
+                        break;
+                    }
+                    thread::sleep(Duration::from_millis(20));
+                }
+                handle.send(MonitorEvent::Stop).unwrap();
+            });
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn stopping_waits_for_manual_restarts() {
+        AppConfig::use_defaults();
+        let monitor = Monitor::new();
+        let handle = monitor.handle();
+        let guard = handle.begin_restart().unwrap();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(300));
+            drop(guard);
+        });
+        handle.send(MonitorEvent::Stop).unwrap();
+
+        let started = Instant::now();
+        let mut checkpoints = Vec::new();
+        monitor.run(|checkpoint, _| checkpoints.push(checkpoint));
+        assert!(started.elapsed() >= Duration::from_millis(300));
+        assert!(checkpoints.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", checkpoints);
+        assert!(handle.begin_restart().is_none());
+        assert!(handle.restart(None).is_none());
+    }
+
+    #[test]
+    fn scripted_events_drive_the_monitor() {
+        AppConfig::use_defaults();
+        let seen = Arc::new(Mutex::new(None));
+        let script = Script { seen: seen.clone() };
+        run_foreground(vec![Box::new(script)]).unwrap();
+
+        let check = seen.lock().unwrap().take().expect("the check reached the worker");
+        assert_eq!(check.trigger, Trigger::Manual);
+    }
+
+    #[test]
+    fn forced_checks_are_manual_even_without_fixing() {
+        assert_eq!(check_job(true, false), Job::new(Trigger::Manual, FixPolicy::Never));
+        assert_eq!(check_job(true, true), Job::new(Trigger::Manual, FixPolicy::Always));
+        assert_eq!(check_job(false, true), Job::new(Trigger::Periodic, FixPolicy::UnlessPaused));
+        assert_eq!(check_job(false, false), Job::new(Trigger::Periodic, FixPolicy::Never));
+    }
+}
@@ -0,0 +1,504 @@
+use rumqttc::{Client, Connection, Event, Incoming, LastWill, MqttOptions, QoS, TlsConfiguration, Transport};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::config::AppConfig;
+use crate::history::RestartStatus;
+use crate::monitor::{MonitorEvent, MonitorHandle, MonitorStatus};
+use crate::notify::Notification;
+use crate::worker::AdapterState;
+
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+// Status changes outside checks, e.g. a pause, show up within this
+const STATE_POLL_INTERVAL: Duration = Duration::from_secs(5);
+const REQUEST_CAPACITY: usize = 64;
+const ONLINE: &str = "online";
+const OFFLINE: &str = "offline";
+
+/// MQTT publisher with Home Assistant discovery
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct MqttConfig {
+    /// Connect to the broker and publish state and events
+    pub enabled: bool,
+    /// Broker host name or address
+    pub host: String,
+    /// Broker port, usually 1883 or 8883 with TLS
+    pub port: u16,
+    /// Client ID, defaults to relink-<service_name>
+    pub client_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// Connect with TLS, verified against the system certificate store
+    pub tls: bool,
+    /// PEM file with the CA that signed the broker's certificate, for private CAs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ca_file: Option<PathBuf>,
+    /// Topics are <topic_prefix>/<service_name>/{state,event,availability,command}
+    pub topic_prefix: String,
+    /// Home Assistant discovery prefix, empty to skip discovery
+    pub discovery_prefix: String,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: "localhost".to_string(),
+            port: 1883,
+            client_id: String::new(),
+            username: None,
+            password: None,
+            tls: false,
+            ca_file: None,
+            topic_prefix: "relink".to_string(),
+            discovery_prefix: "homeassistant".to_string(),
+        }
+    }
+}
+
+// Retained summary of the monitored adapter, what Home Assistant entities read
+#[derive(Serialize, PartialEq)]
+struct StatePayload {
+    state: AdapterState,
+    degraded: bool,
+    speed_bps: Option<u64>,
+    speed_mbps: Option<f64>,
+    threshold_bps: u64,
+    paused: bool,
+    breaker_open: bool,
+    breaker_remaining: u32,
+    last_check: Option<u64>,
+    last_restart: Option<u64>,
+    last_restart_status: Option<RestartStatus>,
+}
+
+impl StatePayload {
+    fn from_status(status: &MonitorStatus) -> Option<Self> {
+        let adapter = status.adapters.first()?;
+        let speed = adapter.last_check.as_ref().and_then(|check| check.report.speed);
+        Some(Self {
+            state: adapter.state,
+            degraded: adapter.state == AdapterState::Degraded,
+            speed_bps: speed.map(|speed| speed.bps()),
+            speed_mbps: speed.map(|speed| speed.bps() as f64 / 1_000_000.0),
+            threshold_bps: adapter.threshold.bps(),
+            paused: status.paused,
+            breaker_open: status.breaker.open,
+            breaker_remaining: status.breaker.remaining,
+            last_check: adapter.last_check.as_ref().map(|check| check.time),
+            last_restart: adapter.last_restart.as_ref().map(|record| record.time),
+            last_restart_status: adapter.last_restart.as_ref().map(|record| record.status),
+        })
+    }
+}
+
+struct Topics {
+    node_id: String,
+    state: String,
+    event: String,
+    availability: String,
+    command: String,
+}
+
+impl Topics {
+    fn new(config: &MqttConfig, service_name: &str) -> Self {
+        // Topic levels and discovery IDs only allow a safe subset of characters
+        let node_id: String = service_name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+            .collect();
+        let base = format!("{}/{}", config.topic_prefix.trim_end_matches('/'), node_id);
+        Self {
+            state: format!("{}/state", base),
+            event: format!("{}/event", base),
+            availability: format!("{}/availability", base),
+            command: format!("{}/command", base),
+            node_id,
+        }
+    }
+}
+
+// Set while an MQTT-requested restart runs, further requests are ignored meanwhile
+static RESTART_PENDING: AtomicBool = AtomicBool::new(false);
+
+// The connected client, so notifications can be published from any thread
+static EVENTS: Mutex<Option<(Client, String)>> = Mutex::new(None);
+
+pub fn publish_event(notification: &Notification) {
+    let events = EVENTS.lock().unwrap_or_else(|e| e.into_inner());
+    let Some((client, topic)) = events.as_ref() else { return };
+    let payload = serde_json::to_vec(notification).expect("notifications serialize");
+    if let Err(e) = client.try_publish(topic.as_str(), QoS::AtLeastOnce, false, payload) {
+        log::warn!("Failed to publish MQTT event: {}", e);
+    }
+}
+
+// Publishes state and events to the broker and takes commands from it until dropped.
+// Reconnects on its own if the broker goes away.
+pub struct MqttPublisher {
+    client: Client,
+    availability_topic: String,
+    stop: Arc<AtomicBool>,
+}
+
+impl MqttPublisher {
+    pub fn start(config: &MqttConfig, handle: MonitorHandle) -> Result<Self, String> {
+        let service_name = AppConfig::global().service_name.clone();
+        let topics = Arc::new(Topics::new(config, &service_name));
+        let client_id = if config.client_id.is_empty() {
+            format!("relink-{}", topics.node_id)
+        } else {
+            config.client_id.clone()
+        };
+
+        let mut options = MqttOptions::new(client_id, config.host.clone(), config.port);
+        options.set_keep_alive(KEEP_ALIVE);
+        options.set_last_will(LastWill::new(topics.availability.clone(), OFFLINE, QoS::AtLeastOnce, true));
+        if let Some(username) = &config.username {
+            options.set_credentials(username.clone(), config.password.clone().unwrap_or_default());
+        }
+        if config.tls {
+            let tls = match &config.ca_file {
+                Some(path) => TlsConfiguration::SimpleNative {
+                    ca: fs::read(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?,
+                    client_auth: None,
+                },
+                None => TlsConfiguration::Native,
+            };
+            options.set_transport(Transport::Tls(tls));
+        }
+
+        let (client, connection) = Client::new(options, REQUEST_CAPACITY);
+        let stop = Arc::new(AtomicBool::new(false));
+        let last_state = Arc::new(Mutex::new(None));
+
+        let discovery_prefix = config.discovery_prefix.trim_end_matches('/').to_string();
+        let (events_client, events_topics, events_stop) = (client.clone(), topics.clone(), stop.clone());
+        let (events_state, events_handle) = (last_state.clone(), handle.clone());
+        thread::spawn(move || {
+            run_connection(
+                connection,
+                &events_client,
+                &events_topics,
+                &discovery_prefix,
+                &events_state,
+                &events_handle,
+                &events_stop,
+            )
+        });
+
+        let (state_client, state_topics, state_stop) = (client.clone(), topics.clone(), stop.clone());
+        thread::spawn(move || {
+            while !state_stop.load(Ordering::SeqCst) {
+                publish_state(&state_client, &state_topics, &last_state, &handle);
+                thread::sleep(STATE_POLL_INTERVAL);
+            }
+        });
+
+        *EVENTS.lock().unwrap_or_else(|e| e.into_inner()) = Some((client.clone(), topics.event.clone()));
+        log::info!("Publishing to MQTT broker {}:{} under {}.", config.host, config.port, topics.state);
+        Ok(Self {
+            client,
+            availability_topic: topics.availability.clone(),
+            stop,
+        })
+    }
+}
+
+impl Drop for MqttPublisher {
+    fn drop(&mut self) {
+        *EVENTS.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        self.stop.store(true, Ordering::SeqCst);
+        // A clean disconnect doesn't trigger the last will
+        let _ = self.client.try_publish(self.availability_topic.as_str(), QoS::AtLeastOnce, true, OFFLINE);
+        let _ = self.client.try_disconnect();
+    }
+}
+
+fn run_connection(
+    mut connection: Connection,
+    client: &Client,
+    topics: &Topics,
+    discovery_prefix: &str,
+    last_state: &Mutex<Option<String>>,
+    handle: &MonitorHandle,
+    stop: &AtomicBool,
+) {
+    let mut connected = false;
+    for event in connection.iter() {
+        match event {
+            Ok(Event::Incoming(Incoming::ConnAck(_))) => {
+                log::info!("Connected to the MQTT broker.");
+                connected = true;
+                let _ = client.try_subscribe(topics.command.as_str(), QoS::AtLeastOnce);
+                if !discovery_prefix.is_empty() {
+                    for (topic, payload) in discovery_messages(topics, discovery_prefix) {
+                        let _ = client.try_publish(topic, QoS::AtLeastOnce, true, payload.to_string());
+                    }
+                }
+                let _ = client.try_publish(topics.availability.as_str(), QoS::AtLeastOnce, true, ONLINE);
+                // The broker may have lost retained messages, publish the state again
+                *last_state.lock().unwrap_or_else(|e| e.into_inner()) = None;
+            }
+            Ok(Event::Incoming(Incoming::Publish(message))) if message.topic == topics.command => {
+                run_command(String::from_utf8_lossy(&message.payload).trim(), handle);
+            }
+            Ok(_) => {}
+            Err(_) if stop.load(Ordering::SeqCst) => break,
+            Err(e) => {
+                if connected {
+                    log::warn!("Lost the MQTT connection, reconnecting: {}", e);
+                } else {
+                    log::debug!("MQTT connection failed: {}", e);
+                }
+                connected = false;
+                thread::sleep(RECONNECT_DELAY);
+            }
+        }
+    }
+}
+
+fn publish_state(client: &Client, topics: &Topics, last_state: &Mutex<Option<String>>, handle: &MonitorHandle) {
+    let Some(state) = StatePayload::from_status(&handle.status()) else { return };
+    let payload = serde_json::to_string(&state).expect("state serializes");
+    let mut last_state = last_state.lock().unwrap_or_else(|e| e.into_inner());
+    if last_state.as_deref() == Some(payload.as_str()) {
+        return;
+    }
+    if client.try_publish(topics.state.as_str(), QoS::AtLeastOnce, true, payload.clone()).is_ok() {
+        *last_state = Some(payload);
+    }
+}
+
+fn run_command(command: &str, handle: &MonitorHandle) {
+    log::info!("MQTT command '{}' received.", command);
+    let event = match command {
+        "check" => MonitorEvent::Check,
+        "pause" => MonitorEvent::Pause,
+        "resume" => MonitorEvent::Resume,
+        "reset_breaker" => MonitorEvent::ResetBreaker,
+        "reload" => MonitorEvent::ReloadConfig,
+        // Takes up to a couple of minutes, keep the connection serviced meanwhile. Counted
+        // as running from here, so stopping the monitor waits for it.
+        "restart" => {
+            let Some(guard) = handle.begin_restart() else {
+                log::warn!("Ignoring MQTT command 'restart', monitoring is stopping.");
+                return;
+            };
+            if RESTART_PENDING.swap(true, Ordering::SeqCst) {
+                log::warn!("Ignoring MQTT command 'restart', the previous restart is still pending.");
+                return;
+            }
+            let handle = handle.clone();
+            thread::spawn(move || {
+                handle.restart_counted(guard, None);
+                RESTART_PENDING.store(false, Ordering::SeqCst);
+            });
+            return;
+        }
+        _ => {
+            log::warn!("Unknown MQTT command '{}', expected check, restart, pause, resume, reset_breaker or reload.", command);
+            return;
+        }
+    };
+    if let Err(e) = handle.send(event) {
+        log::error!("MQTT command '{}' failed: {}", command, e);
+    }
+}
+
+// Retained config messages that make Home Assistant create the entities
+fn discovery_messages(topics: &Topics, discovery_prefix: &str) -> Vec<(String, Value)> {
+    let config = AppConfig::global();
+    let device = json!({
+        "identifiers": [format!("relink_{}", topics.node_id)],
+        "name": config.service_display_name,
+        "manufacturer": "Relink",
+        "model": "Relink Network Monitor",
+        "sw_version": env!("CARGO_PKG_VERSION"),
+    });
+    let entities = [
+        ("sensor", "link_speed", json!({
+            "name": "Link speed",
+            "unit_of_measurement": "Mbit/s",
+            "device_class": "data_rate",
+            "state_class": "measurement",
+            "value_template": "{{ value_json.speed_mbps }}",
+        })),
+        ("sensor", "state", json!({
+            "name": "State",
+            "value_template": "{{ value_json.state }}",
+        })),
+        ("sensor", "breaker_remaining", json!({
+            "name": "Restarts left",
+            "entity_category": "diagnostic",
+            "value_template": "{{ value_json.breaker_remaining }}",
+        })),
+        ("binary_sensor", "degraded", json!({
+            "name": "Link degraded",
+            "device_class": "problem",
+            "value_template": "{{ 'ON' if value_json.degraded else 'OFF' }}",
+        })),
+        ("binary_sensor", "breaker_open", json!({
+            "name": "Circuit breaker open",
+            "device_class": "problem",
+            "entity_category": "diagnostic",
+            "value_template": "{{ 'ON' if value_json.breaker_open else 'OFF' }}",
+        })),
+        ("button", "check", json!({
+            "name": "Check link",
+            "command_topic": topics.command,
+            "payload_press": "check",
+        })),
+        ("button", "restart", json!({
+            "name": "Restart adapter",
+            "device_class": "restart",
+            "command_topic": topics.command,
+            "payload_press": "restart",
+        })),
+    ];
+
+    entities
+        .into_iter()
+        .map(|(component, object_id, mut payload)| {
+            let entity = payload.as_object_mut().expect("entity configs are objects");
+            entity.insert("unique_id".to_string(), json!(format!("relink_{}_{}", topics.node_id, object_id)));
+            entity.insert("availability_topic".to_string(), json!(topics.availability));
+            entity.insert("device".to_string(), device.clone());
+            if component != "button" {
+                entity.insert("state_topic".to_string(), json!(topics.state));
+            }
+            let topic = format!("{}/{}/{}/{}/config", discovery_prefix, component, topics.node_id, object_id);
+            (topic, payload)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::{CheckOutcome, CheckReport};
+    use crate::history::{RestartCause, RestartRecord};
+    use crate::monitor::{AdapterStatus, BreakerStatus};
+    use crate::units::{Interval, LinkSpeed};
+    use crate::worker::{LastCheck, Trigger};
+
+    fn topics() -> Topics {
+        let config = MqttConfig {
+            topic_prefix: "home/relink/".to_string(),
+            ..MqttConfig::default()
+        };
+        Topics::new(&config, "Relink Monitor-2")
+    }
+
+    #[test]
+    fn topics_use_a_safe_node_id() {
+        let topics = topics();
+        assert_eq!(topics.node_id, "relink_monitor_2");
+        assert_eq!(topics.state, "home/relink/relink_monitor_2/state");
+        assert_eq!(topics.event, "home/relink/relink_monitor_2/event");
+        assert_eq!(topics.availability, "home/relink/relink_monitor_2/availability");
+        assert_eq!(topics.command, "home/relink/relink_monitor_2/command");
+    }
+
+    #[test]
+    fn discovery_describes_every_entity() {
+        AppConfig::use_defaults();
+        let topics = topics();
+        let messages = discovery_messages(&topics, "homeassistant");
+        let names: Vec<&str> = messages.iter().map(|(topic, _)| topic.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "homeassistant/sensor/relink_monitor_2/link_speed/config",
+                "homeassistant/sensor/relink_monitor_2/state/config",
+                "homeassistant/sensor/relink_monitor_2/breaker_remaining/config",
+                "homeassistant/binary_sensor/relink_monitor_2/degraded/config",
+                "homeassistant/binary_sensor/relink_monitor_2/breaker_open/config",
+                "homeassistant/button/relink_monitor_2/check/config",
+                "homeassistant/button/relink_monitor_2/restart/config",
+            ]
+        );
+        for (topic, payload) in &messages {
+            let object_id = topic.split('/').nth(3).unwrap();
+            assert_eq!(payload["unique_id"], format!("relink_relink_monitor_2_{}", object_id));
+            assert_eq!(payload["availability_topic"], topics.availability);
+            assert_eq!(payload["device"]["identifiers"][0], "relink_relink_monitor_2");
+            if topic.contains("/button/") {
+                assert_eq!(payload["command_topic"], topics.command);
+                assert!(payload.get("state_topic").is_none());
+            } else {
+                assert_eq!(payload["state_topic"], topics.state);
+            }
+        }
+    }
+
+    fn status(adapters: Vec<AdapterStatus>) -> MonitorStatus {
+        MonitorStatus {
+            pid: 1,
+            version: "0.1.0".to_string(),
+            service_name: "Relink".to_string(),
+            config_path: PathBuf::from("relink.toml"),
+            config_version: 3,
+            paused: true,
+            last_wake: None,
+            breaker: BreakerStatus {
+                open: false,
+                remaining: 2,
+                max_restarts: 3,
+                window: Interval::from_secs(3600),
+            },
+            adapters,
+        }
+    }
+
+    #[test]
+    fn state_payload_summarizes_the_adapter() {
+        assert!(StatePayload::from_status(&status(Vec::new())).is_none());
+
+        let mut restart = RestartRecord::new("Ethernet", RestartCause::Automatic, Some(LinkSpeed(100_000_000)));
+        restart.time = 1_700_000_000;
+        restart.status = RestartStatus::StillDegraded;
+        let report = CheckReport {
+            adapter: "Ethernet".to_string(),
+            threshold: LinkSpeed(1_000_000_000),
+            speed: Some(LinkSpeed(100_000_000)),
+            outcome: CheckOutcome::NotFixed { reason: "still degraded".to_string() },
+            restart: None,
+        };
+        let adapter = AdapterStatus {
+            adapter: "Ethernet".to_string(),
+            threshold: LinkSpeed(1_000_000_000),
+            state: AdapterState::Degraded,
+            last_check: Some(LastCheck { time: 1_700_000_060, trigger: Trigger::Periodic, report }),
+            last_restart: Some(restart),
+        };
+        let payload = StatePayload::from_status(&status(vec![adapter])).unwrap();
+        assert_eq!(
+            serde_json::to_value(&payload).unwrap(),
+            json!({
+                "state": "degraded",
+                "degraded": true,
+                "speed_bps": 100_000_000,
+                "speed_mbps": 100.0,
+                "threshold_bps": 1_000_000_000,
+                "paused": true,
+                "breaker_open": false,
+                "breaker_remaining": 2,
+                "last_check": 1_700_000_060,
+                "last_restart": 1_700_000_000,
+                "last_restart_status": "still_degraded",
+            })
+        );
+    }
+}
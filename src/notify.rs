@@ -0,0 +1,136 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::env;
+
+use crate::config::AppConfig;
+use crate::history::{unix_now, RestartRecord, RestartStatus};
+use crate::mqtt;
+use crate::units::LinkSpeed;
+use crate::webhook;
+use crate::worker::Trigger;
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    Degraded,
+    Restarted,
+    RecoveryFailed,
+    // The pre-restart hook refused a restart
+    Vetoed,
+    BreakerOpened,
+}
+
+// Something worth telling people about, sent to every configured sink
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Notification {
+    pub event: NotificationKind,
+    // Seconds since the Unix epoch
+    pub time: u64,
+    pub host: String,
+    pub service: String,
+    pub adapter: String,
+    pub message: String,
+    pub threshold: LinkSpeed,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speed: Option<LinkSpeed>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trigger: Option<Trigger>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restart: Option<RestartRecord>,
+}
+
+impl Notification {
+    pub fn new(event: NotificationKind, message: String) -> Self {
+        let config = AppConfig::global();
+        Self {
+            event,
+            time: unix_now(),
+            host: env::var("COMPUTERNAME").unwrap_or_default(),
+            service: config.service_name.clone(),
+            adapter: config.target_adapter_name.clone(),
+            message,
+            threshold: config.link_speed_threshold_bps,
+            speed: None,
+            trigger: None,
+            restart: None,
+        }
+    }
+
+    pub fn degraded(speed: LinkSpeed, trigger: Trigger) -> Self {
+        let mut notification = Self::new(
+            NotificationKind::Degraded,
+            format!("Link degraded to {} ({} check).", speed, trigger.name()),
+        );
+        notification.speed = Some(speed);
+        notification.trigger = Some(trigger);
+        notification
+    }
+
+    pub fn restart(record: &RestartRecord) -> Self {
+        let speed_after = record.speed_after.map(|speed| speed.to_string()).unwrap_or_else(|| "unknown".to_string());
+        let (event, message) = match record.status {
+            RestartStatus::Recovered => (
+                NotificationKind::Restarted,
+                format!("Restarted '{}' ({}), link recovered at {}.", record.adapter, record.cause.name(), speed_after),
+            ),
+            RestartStatus::StillDegraded => (
+                NotificationKind::RecoveryFailed,
+                format!("Restarted '{}' ({}), but the link is still at {}.", record.adapter, record.cause.name(), speed_after),
+            ),
+            RestartStatus::DeviceNotFound => (
+                NotificationKind::RecoveryFailed,
+                format!("Could not restart '{}', device not found.", record.adapter),
+            ),
+            RestartStatus::Vetoed => (
+                NotificationKind::Vetoed,
+                format!("Restart of '{}' vetoed: {}", record.adapter, record.error.as_deref().unwrap_or("unknown reason")),
+            ),
+            RestartStatus::Failed => (
+                NotificationKind::RecoveryFailed,
+                format!("Could not restart '{}': {}", record.adapter, record.error.as_deref().unwrap_or("unknown error")),
+            ),
+        };
+        let mut notification = Self::new(event, message);
+        notification.adapter = record.adapter.clone();
+        notification.speed = record.speed_after;
+        notification.restart = Some(record.clone());
+        notification
+    }
+
+    pub fn breaker_opened() -> Self {
+        let config = AppConfig::global();
+        Self::new(
+            NotificationKind::BreakerOpened,
+            format!(
+                "Circuit breaker opened after {} restarts within {}, automatic restarts are suspended.",
+                config.breaker_max_restarts, config.breaker_window_secs
+            ),
+        )
+    }
+}
+
+pub fn publish(notification: Notification) {
+    log::debug!("Notification: {}", notification.message);
+    mqtt::publish_event(&notification);
+    webhook::enqueue(notification);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::RestartCause;
+
+    #[test]
+    fn vetoed_restarts_are_their_own_event() {
+        AppConfig::use_defaults();
+        let mut record = RestartRecord::new("Ethernet", RestartCause::Automatic, None);
+        record.status = RestartStatus::Vetoed;
+        record.error = Some("pre_restart hook exited with exit status: 1".to_string());
+        let notification = Notification::restart(&record);
+        assert_eq!(notification.event, NotificationKind::Vetoed);
+        assert_eq!(notification.message, "Restart of 'Ethernet' vetoed: pre_restart hook exited with exit status: 1");
+
+        record.status = RestartStatus::Failed;
+        assert_eq!(Notification::restart(&record).event, NotificationKind::RecoveryFailed);
+    }
+}
@@ -0,0 +1,390 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::config::AppConfig;
+use crate::device::adapter_reports;
+use crate::history;
+use crate::ipc::{self, endpoint_name, IpcListener, IpcStream};
+use crate::monitor::{MonitorEvent, MonitorHandle};
+
+// JSON-RPC 2.0, one request or response object per line
+const JSONRPC_VERSION: &str = "2.0";
+const DEFAULT_HISTORY_LIMIT: usize = 20;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+// Start of the range JSON-RPC leaves to applications
+const SERVER_ERROR: i64 = -32000;
+// Backoff after a failed accept, doubling up to the maximum while failures continue
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+const MAX_ACCEPT_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Request {
+    jsonrpc: String,
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Response {
+    jsonrpc: String,
+    id: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<RpcError>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct CheckParams {
+    force: bool,
+    no_fix: bool,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RestartParams {
+    adapter: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct HistoryParams {
+    limit: Option<usize>,
+}
+
+// Serves the control API of a running monitor until dropped
+pub struct RpcServer {
+    endpoint: String,
+    stop: Arc<AtomicBool>,
+}
+
+impl RpcServer {
+    pub fn start(endpoint: &str, handle: MonitorHandle) -> io::Result<Self> {
+        let mut listener = IpcListener::bind(endpoint)?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_in_thread = stop.clone();
+
+        thread::spawn(move || {
+            let mut retry_delay = ACCEPT_RETRY_DELAY;
+            while !stop_in_thread.load(Ordering::SeqCst) {
+                let stream = match listener.accept() {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        // Giving up would leave the CLI silently running commands locally
+                        log::error!("Control API failed to accept a connection, retrying in {:?}: {}", retry_delay, e);
+                        thread::sleep(retry_delay);
+                        retry_delay = (retry_delay * 2).min(MAX_ACCEPT_RETRY_DELAY);
+                        continue;
+                    }
+                };
+                retry_delay = ACCEPT_RETRY_DELAY;
+                if stop_in_thread.load(Ordering::SeqCst) {
+                    break;
+                }
+                let handle = handle.clone();
+                thread::spawn(move || serve_connection(stream, &handle));
+            }
+        });
+
+        log::info!("Control API listening on {}", endpoint);
+        Ok(Self {
+            endpoint: endpoint.to_string(),
+            stop,
+        })
+    }
+}
+
+impl Drop for RpcServer {
+    fn drop(&mut self) {
+        ipc::stop_accept_loop(&self.stop, || ipc::connect(&self.endpoint));
+    }
+}
+
+fn serve_connection(stream: IpcStream, handle: &MonitorHandle) {
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(e) => {
+            log::warn!("Control API connection failed: {}", e);
+            return;
+        }
+    };
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else { break };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = handle_line(&line, handle);
+        let encoded = serde_json::to_string(&response).expect("responses serialize");
+        if writeln!(writer, "{}", encoded).and_then(|_| writer.flush()).is_err() {
+            break;
+        }
+    }
+}
+
+fn handle_line(line: &str, handle: &MonitorHandle) -> Response {
+    let request = serde_json::from_str::<Value>(line)
+        .map_err(|e| (Value::Null, RpcError::new(PARSE_ERROR, e.to_string())))
+        .and_then(|value| {
+            // Valid JSON that isn't a request still gets its id back where it has one
+            let id = value.get("id").cloned().unwrap_or(Value::Null);
+            serde_json::from_value::<Request>(value).map_err(|e| (id, RpcError::new(INVALID_REQUEST, e.to_string())))
+        });
+    let (id, result) = match request {
+        Err((id, e)) => (id, Err(e)),
+        Ok(request) if request.jsonrpc != JSONRPC_VERSION => {
+            (request.id, Err(RpcError::new(INVALID_REQUEST, "jsonrpc must be \"2.0\"")))
+        }
+        Ok(request) => {
+            let result = dispatch(&request.method, request.params, handle);
+            (request.id, result)
+        }
+    };
+    let (result, error) = match result {
+        Ok(value) => (Some(value), None),
+        Err(e) => (None, Some(e)),
+    };
+    Response {
+        jsonrpc: JSONRPC_VERSION.to_string(),
+        id,
+        result,
+        error,
+    }
+}
+
+fn dispatch(method: &str, params: Value, handle: &MonitorHandle) -> Result<Value, RpcError> {
+    match method {
+        "status" => to_value(handle.status()),
+        "adapters" => {
+            let reports = adapter_reports(&AppConfig::global().target_adapter_name)
+                .map_err(|e| RpcError::new(SERVER_ERROR, format!("failed to list adapters: {}", e)))?;
+            to_value(reports)
+        }
+        "check" => {
+            let params: CheckParams = parse_params(params)?;
+            let report = handle
+                .check(params.force, !params.no_fix)
+                .recv()
+                .map_err(|_| RpcError::new(SERVER_ERROR, "the monitor stopped before the check ran"))?;
+            to_value(report)
+        }
+        "restart" => {
+            // null when another restart still holds the lock
+            let params: RestartParams = parse_params(params)?;
+            to_value(handle.restart(params.adapter))
+        }
+        "history" => {
+            let params: HistoryParams = parse_params(params)?;
+            let records = history::load()
+                .map_err(|e| RpcError::new(SERVER_ERROR, format!("failed to read history: {}", e)))?;
+            let limit = params.limit.unwrap_or(DEFAULT_HISTORY_LIMIT);
+            to_value(&records[records.len().saturating_sub(limit)..])
+        }
+        "pause" => send_event(handle, MonitorEvent::Pause),
+        "resume" => send_event(handle, MonitorEvent::Resume),
+        "reset_breaker" => send_event(handle, MonitorEvent::ResetBreaker),
+        "reload" => send_event(handle, MonitorEvent::ReloadConfig),
+        _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("unknown method '{}'", method))),
+    }
+}
+
+fn send_event(handle: &MonitorHandle, event: MonitorEvent) -> Result<Value, RpcError> {
+    handle
+        .send(event)
+        .map(|()| Value::Null)
+        .map_err(|e| RpcError::new(SERVER_ERROR, e.to_string()))
+}
+
+fn parse_params<T: DeserializeOwned + Default>(params: Value) -> Result<T, RpcError> {
+    if params.is_null() {
+        return Ok(T::default());
+    }
+    serde_json::from_value(params).map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))
+}
+
+fn to_value<T: Serialize>(value: T) -> Result<Value, RpcError> {
+    serde_json::to_value(value).map_err(|e| RpcError::new(SERVER_ERROR, e.to_string()))
+}
+
+#[derive(Debug)]
+pub enum ClientError {
+    NotRunning(String),
+    Io(io::Error),
+    Rpc(RpcError),
+    InvalidResponse(String),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::NotRunning(endpoint) => write!(f, "The service is not running (no control API at {})", endpoint),
+            ClientError::Io(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+                write!(f, "Access to the control API was denied, run as administrator")
+            }
+            ClientError::Io(e) => write!(f, "Control API connection failed: {}", e),
+            ClientError::Rpc(e) => write!(f, "{}", e.message),
+            ClientError::InvalidResponse(message) => write!(f, "Invalid response from the service: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl ClientError {
+    // No service to ask, or one this user may not talk to. Work that doesn't need the
+    // service's state can then run in the calling process.
+    pub fn is_unavailable(&self) -> bool {
+        match self {
+            ClientError::NotRunning(_) => true,
+            ClientError::Io(e) => e.kind() == io::ErrorKind::PermissionDenied,
+            _ => false,
+        }
+    }
+}
+
+// Calls a method on the service this config belongs to
+pub fn call<T: DeserializeOwned>(method: &str, params: Value) -> Result<T, ClientError> {
+    call_at(&endpoint_name(&AppConfig::global().service_name), method, params)
+}
+
+fn call_at<T: DeserializeOwned>(endpoint: &str, method: &str, params: Value) -> Result<T, ClientError> {
+    let stream = ipc::connect(endpoint).map_err(|e| {
+        if ipc::is_not_running(&e) {
+            ClientError::NotRunning(endpoint.to_string())
+        } else {
+            ClientError::Io(e)
+        }
+    })?;
+
+    let request = json!({ "jsonrpc": JSONRPC_VERSION, "id": 1, "method": method, "params": params });
+    let mut writer = stream.try_clone().map_err(ClientError::Io)?;
+    writeln!(writer, "{}", request).and_then(|_| writer.flush()).map_err(ClientError::Io)?;
+
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line).map_err(ClientError::Io)?;
+    let response: Response =
+        serde_json::from_str(&line).map_err(|e| ClientError::InvalidResponse(e.to_string()))?;
+    if let Some(error) = response.error {
+        return Err(ClientError::Rpc(error));
+    }
+    serde_json::from_value(response.result.unwrap_or(Value::Null))
+        .map_err(|e| ClientError::InvalidResponse(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::Monitor;
+
+    fn handle() -> MonitorHandle {
+        AppConfig::use_defaults();
+        Monitor::new().handle()
+    }
+
+    fn error_code(response: &Response) -> Option<i64> {
+        response.error.as_ref().map(|error| error.code)
+    }
+
+    #[test]
+    fn invalid_json_is_a_parse_error() {
+        let response = handle_line("{not json", &handle());
+        assert_eq!(error_code(&response), Some(PARSE_ERROR));
+        assert_eq!(response.id, Value::Null);
+        assert!(response.result.is_none());
+    }
+
+    #[test]
+    fn json_that_is_not_a_request_is_invalid() {
+        let response = handle_line(r#"{"jsonrpc":"2.0","id":3}"#, &handle());
+        assert_eq!(error_code(&response), Some(INVALID_REQUEST));
+        assert_eq!(response.id, json!(3));
+    }
+
+    #[test]
+    fn other_jsonrpc_versions_are_rejected() {
+        let response = handle_line(r#"{"jsonrpc":"1.0","id":7,"method":"status"}"#, &handle());
+        assert_eq!(error_code(&response), Some(INVALID_REQUEST));
+        assert_eq!(response.id, json!(7));
+    }
+
+    #[test]
+    fn unknown_methods_are_not_found() {
+        let response = handle_line(r#"{"jsonrpc":"2.0","id":1,"method":"reboot"}"#, &handle());
+        assert_eq!(error_code(&response), Some(METHOD_NOT_FOUND));
+    }
+
+    #[test]
+    fn bad_params_are_invalid() {
+        let handle = handle();
+        for params in [r#"{"force":"yes"}"#, r#"{"bogus":true}"#, "[1]"] {
+            let line = format!(r#"{{"jsonrpc":"2.0","id":1,"method":"check","params":{}}}"#, params);
+            assert_eq!(error_code(&handle_line(&line, &handle)), Some(INVALID_PARAMS), "{}", params);
+        }
+    }
+
+    #[test]
+    fn pause_and_resume_change_the_status() {
+        let handle = handle();
+        let response = handle_line(r#"{"jsonrpc":"2.0","id":"a","method":"pause"}"#, &handle);
+        assert!(response.error.is_none());
+        assert_eq!(response.id, json!("a"));
+        assert_eq!(response.result, Some(Value::Null));
+        assert!(handle.status().paused);
+
+        handle_line(r#"{"jsonrpc":"2.0","id":"b","method":"resume"}"#, &handle);
+        assert!(!handle.status().paused);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn clients_call_a_running_server() {
+        let dir = std::env::temp_dir().join(format!("relink-rpc-test-{}", std::process::id()));
+        let endpoint = dir.join("control.sock").to_string_lossy().into_owned();
+        let handle = handle();
+        let server = RpcServer::start(&endpoint, handle.clone()).unwrap();
+
+        call_at::<Value>(&endpoint, "pause", Value::Null).unwrap();
+        assert!(handle.status().paused);
+        let status: Value = call_at(&endpoint, "status", Value::Null).unwrap();
+        assert_eq!(status["paused"], json!(true));
+        match call_at::<Value>(&endpoint, "reboot", Value::Null) {
+            Err(ClientError::Rpc(error)) => assert_eq!(error.code, METHOD_NOT_FOUND),
+            other => panic!("expected an RPC error, got {:?}", other),
+        }
+
+        // The accept loop stops and removes the socket shortly after the drop
+        drop(server);
+        let stopped = (0..50).any(|_| {
+            thread::sleep(Duration::from_millis(20));
+            matches!(call_at::<Value>(&endpoint, "status", Value::Null), Err(ClientError::NotRunning(_)))
+        });
+        assert!(stopped);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,39 @@
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::{LocalFree, HLOCAL};
+use windows::Win32::Security::Authorization::{ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1};
+use windows::Win32::Security::{PSECURITY_DESCRIPTOR, SECURITY_ATTRIBUTES};
+
+// SYSTEM (the service) and administrators (the CLI) get full access, nobody else any
+pub const ADMIN_ONLY_SDDL: PCWSTR = windows::core::w!("D:P(A;;GA;;;SY)(A;;GA;;;BA)");
+
+// Security attributes built from an SDDL string, for objects shared with other processes
+pub struct SecurityAttributes {
+    attributes: SECURITY_ATTRIBUTES,
+}
+
+impl SecurityAttributes {
+    pub fn from_sddl(sddl: PCWSTR) -> windows::core::Result<Self> {
+        let mut descriptor = PSECURITY_DESCRIPTOR::default();
+        unsafe { ConvertStringSecurityDescriptorToSecurityDescriptorW(sddl, SDDL_REVISION_1, &mut descriptor, None)? };
+        Ok(Self {
+            attributes: SECURITY_ATTRIBUTES {
+                nLength: size_of::<SECURITY_ATTRIBUTES>() as u32,
+                lpSecurityDescriptor: descriptor.0,
+                bInheritHandle: false.into(),
+            },
+        })
+    }
+
+    pub fn as_ptr(&self) -> *const SECURITY_ATTRIBUTES {
+        &self.attributes
+    }
+}
+
+// The descriptor is owned by this value and never mutated, so it can move between threads
+unsafe impl Send for SecurityAttributes {}
+
+impl Drop for SecurityAttributes {
+    fn drop(&mut self) {
+        let _ = unsafe { LocalFree(Some(HLOCAL(self.attributes.lpSecurityDescriptor))) };
+    }
+}
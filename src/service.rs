@@ -1,16 +1,100 @@
-use std::{env, thread, ffi::OsString, sync::{Arc, atomic::{AtomicBool, Ordering}}, time::Duration};
+use std::{
+    env,
+    ffi::OsString,
+    sync::{Arc, Mutex, OnceLock},
+    time::Duration,
+};
 use windows_service::{
     service::{
         ServiceControl, ServiceControlAccept, ServiceExitCode, ServiceState, ServiceStatus,
         ServiceType, ServiceAccess, ServiceStartType, ServiceErrorControl, ServiceInfo,
-        PowerEventParam,
+        PowerEventParam, UserEventCode,
     },
-    service_control_handler::{self, ServiceControlHandlerResult},
+    service_control_handler::{self, ServiceControlHandlerResult, ServiceStatusHandle},
     service_manager::{ServiceManager, ServiceManagerAccess},
 };
 
 use crate::config::AppConfig;
-use crate::device::check_and_fix_network;
+use crate::monitor::{Monitor, MonitorEvent};
+
+const ACCEPTED_CONTROLS: ServiceControlAccept = ServiceControlAccept::STOP
+    .union(ServiceControlAccept::POWER_EVENT)
+    .union(ServiceControlAccept::PAUSE_CONTINUE);
+
+// Also reachable with `sc control`. User-defined control codes must lie in 128..=255
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ServiceCommand {
+    Check,
+    ReloadConfig,
+    ResetBreaker,
+}
+
+impl ServiceCommand {
+    fn code(self) -> UserEventCode {
+        let raw = match self {
+            ServiceCommand::Check => 128,
+            ServiceCommand::ReloadConfig => 129,
+            ServiceCommand::ResetBreaker => 130,
+        };
+        UserEventCode::from_raw(raw).expect("user control codes are within 128..=255")
+    }
+
+    fn from_code(code: UserEventCode) -> Option<Self> {
+        [ServiceCommand::Check, ServiceCommand::ReloadConfig, ServiceCommand::ResetBreaker]
+            .into_iter()
+            .find(|command| command.code() == code)
+    }
+}
+
+// Keeps the last reported status so Interrogate can re-report it from the handler thread.
+struct StatusReporter {
+    handle: OnceLock<ServiceStatusHandle>,
+    current: Mutex<ServiceStatus>,
+}
+
+impl StatusReporter {
+    fn new() -> Self {
+        Self {
+            handle: OnceLock::new(),
+            current: Mutex::new(ServiceStatus {
+                service_type: ServiceType::OWN_PROCESS,
+                current_state: ServiceState::StartPending,
+                controls_accepted: ServiceControlAccept::empty(),
+                exit_code: ServiceExitCode::Win32(0),
+                checkpoint: 0,
+                wait_hint: Duration::default(),
+                process_id: None,
+            }),
+        }
+    }
+
+    fn set(
+        &self,
+        state: ServiceState,
+        controls_accepted: ServiceControlAccept,
+        checkpoint: u32,
+        wait_hint: Duration,
+    ) -> windows_service::Result<()> {
+        let mut current = self.current.lock().unwrap_or_else(|e| e.into_inner());
+        current.current_state = state;
+        current.controls_accepted = controls_accepted;
+        current.checkpoint = checkpoint;
+        current.wait_hint = wait_hint;
+        match self.handle.get() {
+            Some(handle) => handle.set_service_status(current.clone()),
+            None => Ok(()),
+        }
+    }
+
+    fn report_current(&self) {
+        let current = self.current.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(handle) = self.handle.get() {
+            if let Err(e) = handle.set_service_status(current.clone()) {
+                log::warn!("Failed to report service status: {}", e);
+            }
+        }
+    }
+}
 
 // Service Entry Point
 pub fn my_service_main(_arguments: Vec<OsString>) {
@@ -20,27 +104,58 @@ pub fn my_service_main(_arguments: Vec<OsString>) {
 }
 
 fn run_service() -> windows_service::Result<()> {
-    let running = Arc::new(AtomicBool::new(true));
-    let running_in_handler = running.clone();
+    let reporter = Arc::new(StatusReporter::new());
+    let reporter_in_handler = reporter.clone();
+
+    let monitor = Monitor::new();
+    let handle = monitor.handle();
+
+    // Pausing through the control API shows up in the SCM too
+    let reporter_on_pause = reporter.clone();
+    monitor.on_pause_change(move |paused| {
+        let state = if paused { ServiceState::Paused } else { ServiceState::Running };
+        if let Err(e) = reporter_on_pause.set(state, ACCEPTED_CONTROLS, 0, Duration::default()) {
+            log::warn!("Failed to report service status: {}", e);
+        }
+    });
     
     let event_handler = move |control_event| -> ServiceControlHandlerResult {
         match control_event {
-            ServiceControl::Stop | ServiceControl::Interrogate => {
-                running_in_handler.store(false, Ordering::SeqCst);
+            ServiceControl::Stop => {
+                let _ = handle.send(MonitorEvent::Stop);
                 ServiceControlHandlerResult::NoError
             }
+            ServiceControl::Interrogate => {
+                reporter_in_handler.report_current();
+                ServiceControlHandlerResult::NoError
+            }
+            ServiceControl::Pause => {
+                let _ = handle.send(MonitorEvent::Pause);
+                ServiceControlHandlerResult::NoError
+            }
+            ServiceControl::Continue => {
+                let _ = handle.send(MonitorEvent::Resume);
+                ServiceControlHandlerResult::NoError
+            }
+            ServiceControl::UserEvent(code) => {
+                let event = match ServiceCommand::from_code(code) {
+                    Some(ServiceCommand::Check) => MonitorEvent::Check,
+                    Some(ServiceCommand::ResetBreaker) => MonitorEvent::ResetBreaker,
+                    Some(ServiceCommand::ReloadConfig) => MonitorEvent::ReloadConfig,
+                    None => return ServiceControlHandlerResult::NotImplemented,
+                };
+                match handle.send(event) {
+                    Ok(()) => ServiceControlHandlerResult::NoError,
+                    Err(e) => {
+                        log::error!("Config reload failed, keeping current config: {}", e);
+                        ServiceControlHandlerResult::Other(1)
+                    }
+                }
+            }
             ServiceControl::PowerEvent(event_param) => {
                 match event_param {
                     PowerEventParam::ResumeAutomatic | PowerEventParam::ResumeSuspend => {
-                        log::info!("System wake detected (Automatic/Suspend).");
-                        
-                        thread::spawn(|| {
-                             let wait_time = Duration::from_secs(AppConfig::global().wait_after_wake_secs);
-                            log::info!("Waiting {:?} for network adapter initialization...", wait_time);
-                            thread::sleep(wait_time);
-                            // FORCE check because this is a wake event
-                            check_and_fix_network(true);
-                        });
+                        let _ = handle.send(MonitorEvent::Wake);
                     }
                     _ => {}
                 }
@@ -52,41 +167,19 @@ fn run_service() -> windows_service::Result<()> {
 
     let config = AppConfig::global();
     let status_handle = service_control_handler::register(&config.service_name, event_handler)?;
+    let _ = reporter.handle.set(status_handle);
 
-    // Report Running state
-    let next_status = ServiceStatus {
-        service_type: ServiceType::OWN_PROCESS,
-        current_state: ServiceState::Running,
-        controls_accepted: ServiceControlAccept::STOP | ServiceControlAccept::POWER_EVENT,
-        exit_code: ServiceExitCode::Win32(0),
-        checkpoint: 0,
-        wait_hint: Duration::default(),
-        process_id: None,
-    };
-    status_handle.set_service_status(next_status)?;
+    reporter.set(ServiceState::Running, ACCEPTED_CONTROLS, 0, Duration::default())?;
     
     log::info!("Service started successfully.");
-    
-    thread::spawn(|| {
-        thread::sleep(Duration::from_secs(5));
-        check_and_fix_network(false); 
-    });
 
-    // Main loop
-    while running.load(Ordering::SeqCst) {
-        thread::sleep(Duration::from_secs(60));
-        check_and_fix_network(false);
-    }
+    monitor.run(|checkpoint, wait_hint| {
+        if let Err(e) = reporter.set(ServiceState::StopPending, ServiceControlAccept::empty(), checkpoint, wait_hint) {
+            log::warn!("Failed to report service status: {}", e);
+        }
+    });
     
-    status_handle.set_service_status(ServiceStatus {
-        service_type: ServiceType::OWN_PROCESS,
-        current_state: ServiceState::Stopped,
-        controls_accepted: ServiceControlAccept::empty(),
-        exit_code: ServiceExitCode::Win32(0),
-        checkpoint: 0,
-        wait_hint: Duration::default(),
-        process_id: None,
-    })?;
+    reporter.set(ServiceState::Stopped, ServiceControlAccept::empty(), 0, Duration::default())?;
     
     log::info!("Service stopped.");
     Ok(())
@@ -98,8 +191,11 @@ pub fn install_service() -> windows_service::Result<()> {
     let service_manager = ServiceManager::local_computer(None::<&str>, manager_access)?;
 
     let service_path = env::current_exe()
-        .map_err(|e| windows_service::Error::Winapi(e))?;
+        .map_err(windows_service::Error::Winapi)?;
     
+    // The service account has its own environment, so pin the config path explicitly
+    let launch_arguments = vec![OsString::from("--config"), AppConfig::get_path().into_os_string()];
+
     let service_info = ServiceInfo {
         name: OsString::from(&config.service_name),
         display_name: OsString::from(&config.service_display_name),
@@ -107,7 +203,7 @@ pub fn install_service() -> windows_service::Result<()> {
         start_type: ServiceStartType::AutoStart,
         error_control: ServiceErrorControl::Normal,
         executable_path: service_path,
-        launch_arguments: Vec::new(),
+        launch_arguments,
         dependencies: Vec::new(),
         account_name: None,
         account_password: None,
@@ -143,4 +239,4 @@ pub fn uninstall_service() -> windows_service::Result<()> {
 
     service.delete()?;
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,415 @@
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::process;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::history::format_time;
+use crate::logger;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+const WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+// While a TCP server is unreachable, lines are dropped and a reconnect is tried this often
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(30);
+// Structured data ID, with the example enterprise number reserved by RFC 5612
+const SD_ID: &str = "relink@32473";
+
+/// RFC 5424 syslog output
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct SyslogConfig {
+    /// Send log lines to a syslog server
+    pub enabled: bool,
+    pub transport: SyslogTransport,
+    /// host:port for udp and tcp, the socket path for unix (e.g. "/dev/log")
+    pub address: String,
+    /// Facility code, e.g. 1 user, 3 daemon, 16 to 23 local0 to local7
+    #[schemars(range(max = 23))]
+    pub facility: u8,
+    /// APP-NAME of each message
+    pub app_name: String,
+}
+
+impl Default for SyslogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            transport: SyslogTransport::Udp,
+            address: "127.0.0.1:514".to_string(),
+            facility: 3,
+            app_name: "relink".to_string(),
+        }
+    }
+}
+
+impl SyslogConfig {
+    pub fn has_valid_address(&self) -> bool {
+        match self.transport {
+            SyslogTransport::Unix => !self.address.trim().is_empty(),
+            SyslogTransport::Udp | SyslogTransport::Tcp => self
+                .address
+                .rsplit_once(':')
+                .is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok_and(|port| port > 0)),
+        }
+    }
+}
+
+/// udp, tcp with octet-counting framing, or a local unix datagram socket
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SyslogTransport {
+    Udp,
+    Tcp,
+    Unix,
+}
+
+// Syslog severity of a log level, also the journal's PRIORITY
+pub fn severity(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+enum Connection {
+    Udp(UdpSocket),
+    Tcp {
+        addresses: Vec<SocketAddr>,
+        stream: Option<TcpStream>,
+        next_attempt: Instant,
+    },
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixDatagram),
+}
+
+impl Connection {
+    fn open(config: &SyslogConfig) -> io::Result<Self> {
+        match config.transport {
+            SyslogTransport::Udp => {
+                let address = resolve(&config.address)?[0];
+                let local: SocketAddr = if address.is_ipv4() { ([0, 0, 0, 0], 0).into() } else { ([0; 16], 0).into() };
+                let socket = UdpSocket::bind(local)?;
+                socket.connect(address)?;
+                Ok(Connection::Udp(socket))
+            }
+            SyslogTransport::Tcp => {
+                let mut connection = Connection::Tcp {
+                    addresses: resolve(&config.address)?,
+                    stream: None,
+                    next_attempt: Instant::now(),
+                };
+                // An unreachable server at startup is retried later rather than disabling the sink
+                if let Err(e) = connection.send(b"") {
+                    log::warn!("Failed to connect to syslog server {}, retrying later: {}", config.address, e);
+                }
+                Ok(connection)
+            }
+            #[cfg(unix)]
+            SyslogTransport::Unix => {
+                let socket = std::os::unix::net::UnixDatagram::unbound()?;
+                socket.connect(&config.address)?;
+                Ok(Connection::Unix(socket))
+            }
+            #[cfg(not(unix))]
+            SyslogTransport::Unix => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "unix sockets are not available on this platform",
+            )),
+        }
+    }
+
+    // Sends one message, an empty one only connects
+    fn send(&mut self, message: &[u8]) -> io::Result<()> {
+        match self {
+            Connection::Udp(socket) if !message.is_empty() => socket.send(message).map(|_| ()),
+            Connection::Udp(_) => Ok(()),
+            Connection::Tcp {
+                addresses,
+                stream,
+                next_attempt,
+            } => {
+                if stream.is_none() {
+                    if Instant::now() < *next_attempt {
+                        return Err(io::Error::new(io::ErrorKind::NotConnected, "waiting to reconnect"));
+                    }
+                    *next_attempt = Instant::now() + RECONNECT_INTERVAL;
+                    *stream = Some(connect_tcp(addresses)?);
+                }
+                if message.is_empty() {
+                    return Ok(());
+                }
+                // RFC 6587 octet counting, so messages may contain newlines
+                let framed = [format!("{} ", message.len()).as_bytes(), message].concat();
+                let result = stream.as_mut().map_or(Ok(()), |stream| stream.write_all(&framed));
+                if result.is_err() {
+                    *stream = None;
+                }
+                result
+            }
+            #[cfg(unix)]
+            Connection::Unix(socket) if !message.is_empty() => socket.send(message).map(|_| ()),
+            #[cfg(unix)]
+            Connection::Unix(_) => Ok(()),
+        }
+    }
+}
+
+fn resolve(address: &str) -> io::Result<Vec<SocketAddr>> {
+    let addresses: Vec<SocketAddr> = address.to_socket_addrs()?.collect();
+    if addresses.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("{} did not resolve", address)));
+    }
+    Ok(addresses)
+}
+
+fn connect_tcp(addresses: &[SocketAddr]) -> io::Result<TcpStream> {
+    let mut last_error = None;
+    for address in addresses {
+        match TcpStream::connect_timeout(address, CONNECT_TIMEOUT) {
+            Ok(stream) => {
+                stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+                return Ok(stream);
+            }
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| io::ErrorKind::NotFound.into()))
+}
+
+pub struct SyslogSink {
+    level: LevelFilter,
+    facility: u8,
+    hostname: String,
+    app_name: String,
+    connection: Mutex<Connection>,
+}
+
+impl SyslogSink {
+    pub fn connect(config: &SyslogConfig, level: LevelFilter) -> io::Result<Self> {
+        Ok(Self {
+            level,
+            facility: config.facility,
+            hostname: header_field(&hostname(), 255),
+            app_name: header_field(&config.app_name, 48),
+            connection: Mutex::new(Connection::open(config)?),
+        })
+    }
+
+    fn format(&self, record: &Record) -> String {
+        let fields = logger::fields(record);
+        let action = fields.iter().find(|(key, _)| key == "action").map(|(_, value)| value.as_str());
+        let data = if fields.is_empty() {
+            "-".to_string()
+        } else {
+            let params: String = fields
+                .iter()
+                .map(|(key, value)| format!(" {}=\"{}\"", header_field(key, 32), escape_param(value)))
+                .collect();
+            format!("[{}{}]", SD_ID, params)
+        };
+        format!(
+            "<{}>1 {} {} {} {} {} {} \u{feff}{}",
+            u32::from(self.facility) * 8 + u32::from(severity(record.level())),
+            timestamp(),
+            self.hostname,
+            self.app_name,
+            process::id(),
+            header_field(action.unwrap_or_default(), 32),
+            data,
+            record.args()
+        )
+    }
+}
+
+impl Log for SyslogSink {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let message = self.format(record);
+        // Nowhere to report a failure to, logging it would loop back here
+        let _ = self.connection.lock().unwrap_or_else(|e| e.into_inner()).send(message.as_bytes());
+    }
+
+    fn flush(&self) {}
+}
+
+fn hostname() -> String {
+    env::var("COMPUTERNAME")
+        .or_else(|_| fs::read_to_string("/proc/sys/kernel/hostname"))
+        .map(|name| name.trim().to_string())
+        .unwrap_or_default()
+}
+
+// UTC with milliseconds, e.g. 2024-05-01T13:37:00.123Z
+fn timestamp() -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let time = format_time(now.as_secs()).replacen(' ', "T", 1);
+    format!("{}.{:03}Z", time.trim_end_matches('Z'), now.subsec_millis())
+}
+
+// Header fields are printable ASCII without spaces, "-" when empty
+fn header_field(value: &str, max_len: usize) -> String {
+    let field: String = value
+        .chars()
+        .filter(|c| c.is_ascii_graphic())
+        .take(max_len)
+        .collect();
+    if field.is_empty() {
+        "-".to_string()
+    } else {
+        field
+    }
+}
+
+fn escape_param(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    fn config(transport: SyslogTransport, address: SocketAddr) -> SyslogConfig {
+        SyslogConfig {
+            enabled: true,
+            transport,
+            address: address.to_string(),
+            facility: 16,
+            app_name: "relink test".to_string(),
+        }
+    }
+
+    fn record_with<T>(level: Level, message: &str, fields: &[(&str, &str)], use_record: impl FnOnce(&Record) -> T) -> T {
+        use_record(&Record::builder().args(format_args!("{}", message)).level(level).key_values(&fields).build())
+    }
+
+    fn udp_sink() -> (SyslogSink, UdpSocket) {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        server.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let sink = SyslogSink::connect(&config(SyslogTransport::Udp, server.local_addr().unwrap()), LevelFilter::Info).unwrap();
+        (sink, server)
+    }
+
+    #[test]
+    fn formats_rfc5424() {
+        let (sink, _server) = udp_sink();
+        let fields = [("action", "restart"), ("adapter", r#"Intel "x" ]\"#)];
+        let message = record_with(Level::Warn, "Restarting.", &fields, |record| sink.format(record));
+
+        let parts: Vec<&str> = message.splitn(7, ' ').collect();
+        // local0 is facility 16, warnings severity 4
+        assert_eq!(parts[0], "<132>1");
+        assert!(parts[1].ends_with('Z') && parts[1].contains('T'), "{}", parts[1]);
+        assert_eq!(parts[3], "relinktest");
+        assert_eq!(parts[4], process::id().to_string());
+        assert_eq!(parts[5], "restart");
+        assert_eq!(
+            parts[6],
+            "[relink@32473 action=\"restart\" adapter=\"Intel \\\"x\\\" \\]\\\\\"] \u{feff}Restarting."
+        );
+    }
+
+    #[test]
+    fn formats_without_fields() {
+        let (sink, _server) = udp_sink();
+        let message = record_with(Level::Error, "Failed.", &[], |record| sink.format(record));
+        assert!(message.starts_with("<131>1 "));
+        assert!(message.ends_with(" - - \u{feff}Failed."), "{}", message);
+    }
+
+    #[test]
+    fn header_fields_are_printable_ascii() {
+        assert_eq!(header_field("", 48), "-");
+        assert_eq!(header_field("my host\u{e9}", 48), "myhost");
+        assert_eq!(header_field("abcdef", 3), "abc");
+    }
+
+    #[test]
+    fn sends_datagrams_over_udp() {
+        let (sink, server) = udp_sink();
+        record_with(Level::Debug, "Filtered out.", &[], |record| sink.log(record));
+        record_with(Level::Info, "Checked.", &[("action", "check")], |record| sink.log(record));
+
+        let mut buffer = [0; 1024];
+        let len = server.recv(&mut buffer).unwrap();
+        let message = String::from_utf8_lossy(&buffer[..len]);
+        assert!(message.starts_with("<134>1 "));
+        assert!(message.ends_with("\u{feff}Checked."));
+    }
+
+    #[test]
+    fn frames_tcp_messages_by_octet_count() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let sink = SyslogSink::connect(&config(SyslogTransport::Tcp, listener.local_addr().unwrap()), LevelFilter::Info).unwrap();
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+        record_with(Level::Info, "First line\nsecond line", &[], |record| sink.log(record));
+        record_with(Level::Warn, "Next.", &[], |record| sink.log(record));
+        drop(sink);
+
+        let mut received = Vec::new();
+        stream.read_to_end(&mut received).unwrap();
+        let mut rest = received.as_slice();
+        let mut messages = Vec::new();
+        while !rest.is_empty() {
+            let space = rest.iter().position(|&b| b == b' ').unwrap();
+            let len: usize = std::str::from_utf8(&rest[..space]).unwrap().parse().unwrap();
+            messages.push(String::from_utf8(rest[space + 1..space + 1 + len].to_vec()).unwrap());
+            rest = &rest[space + 1 + len..];
+        }
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].ends_with("\u{feff}First line\nsecond line"));
+        assert!(messages[1].starts_with("<132>1 ") && messages[1].ends_with("\u{feff}Next."));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sends_datagrams_over_unix_sockets() {
+        use std::os::unix::net::UnixDatagram;
+
+        let dir = env::temp_dir().join(format!("relink-syslog-test-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("log");
+        let server = UnixDatagram::bind(&path).unwrap();
+        server.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let config = SyslogConfig {
+            address: path.to_str().unwrap().to_string(),
+            ..config(SyslogTransport::Unix, "127.0.0.1:514".parse().unwrap())
+        };
+        let sink = SyslogSink::connect(&config, LevelFilter::Info).unwrap();
+
+        record_with(Level::Debug, "Filtered out.", &[], |record| sink.log(record));
+        record_with(Level::Info, "Checked.", &[("action", "check")], |record| sink.log(record));
+
+        let mut buffer = [0; 1024];
+        let len = server.recv(&mut buffer).unwrap();
+        let message = String::from_utf8_lossy(&buffer[..len]);
+        assert!(message.starts_with("<134>1 "), "{}", message);
+        assert!(message.contains("[relink@32473 action=\"check\"]"), "{}", message);
+        assert!(message.ends_with("\u{feff}Checked."));
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,288 @@
+use schemars::{json_schema, JsonSchema, Schema, SchemaGenerator};
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+// Largest first, matched case-insensitively except for the b, see LinkSpeed::from_str
+const SPEED_UNITS: [(&str, u64); 4] = [
+    ("Gbps", 1_000_000_000),
+    ("Mbps", 1_000_000),
+    ("Kbps", 1_000),
+    ("bps", 1),
+];
+
+const INTERVAL_UNITS: [(&str, u64); 4] = [("h", 3_600_000), ("m", 60_000), ("s", 1_000), ("ms", 1)];
+// No configured delay needs more, and the cap keeps sums like max_restart_duration from overflowing
+const MAX_INTERVAL_SECS: u64 = 365 * 24 * 3600;
+
+// Link speed in bits per second. Parses "2.5Gbps", "100Mbps", "1000bps" or a plain
+// integer number of bits per second.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct LinkSpeed(pub u64);
+
+impl LinkSpeed {
+    pub fn bps(self) -> u64 {
+        self.0
+    }
+}
+
+impl FromStr for LinkSpeed {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // "MBps" would be megabytes, eight times more than the same number in Mbps
+        let trimmed = s.trim_end();
+        if trimmed.to_ascii_lowercase().ends_with("bps") && trimmed.as_bytes()[trimmed.len() - 3] == b'B' {
+            return Err(format!("invalid speed '{}', link speeds are in bits per second (e.g. Mbps), not bytes", s));
+        }
+        parse_with_units(s, &SPEED_UNITS, 1)
+            .map(LinkSpeed)
+            .ok_or_else(|| format!("invalid speed '{}', expected e.g. 100Mbps, 2.5Gbps or bits per second", s))
+    }
+}
+
+impl fmt::Display for LinkSpeed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (unit, scale) = SPEED_UNITS
+            .iter()
+            .find(|(_, scale)| self.0 >= *scale)
+            .copied()
+            .unwrap_or(("bps", 1));
+        write!(f, "{}{}", format_scaled(self.0, scale), unit)
+    }
+}
+
+// A configured delay or window of at most a year. Parses "15s", "2m", "1h", "500ms" or a
+// plain integer number of seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Interval(pub Duration);
+
+impl Interval {
+    pub const fn from_secs(secs: u64) -> Self {
+        Interval(Duration::from_secs(secs))
+    }
+
+    // Like from_secs for user input, rejecting values above the cap
+    fn parse_secs(secs: u64) -> Result<Self, String> {
+        Self::from_millis(secs.saturating_mul(1_000)).ok_or_else(|| out_of_range(&secs.to_string()))
+    }
+
+    fn from_millis(millis: u64) -> Option<Self> {
+        (millis <= MAX_INTERVAL_SECS * 1_000).then(|| Interval(Duration::from_millis(millis)))
+    }
+
+    pub fn duration(self) -> Duration {
+        self.0
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.0.is_zero()
+    }
+}
+
+impl FromStr for Interval {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let millis = parse_with_units(s, &INTERVAL_UNITS, 1_000)
+            .ok_or_else(|| format!("invalid duration '{}', expected e.g. 15s, 2m, 1h or seconds", s))?;
+        Interval::from_millis(millis).ok_or_else(|| out_of_range(s))
+    }
+}
+
+fn out_of_range(value: &str) -> String {
+    format!("duration '{}' is too long, at most {} seconds are allowed", value, MAX_INTERVAL_SECS)
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let millis = self.0.as_millis() as u64;
+        if millis == 0 {
+            return f.write_str("0s");
+        }
+        // Largest unit that represents the value exactly, so 90s stays 90s rather than 1.5m
+        let (unit, scale) = INTERVAL_UNITS
+            .iter()
+            .find(|(_, scale)| millis >= *scale && millis.is_multiple_of(*scale))
+            .copied()
+            .unwrap_or(("ms", 1));
+        write!(f, "{}{}", millis / scale, unit)
+    }
+}
+
+// "<number><unit>" with an optional fraction, or a bare integer in `bare_scale` units.
+// Fractions finer than the smallest unit are rejected rather than rounded away.
+fn parse_with_units(s: &str, units: &[(&str, u64)], bare_scale: u64) -> Option<u64> {
+    let s = s.trim();
+    let split = s.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let unit = unit.trim();
+
+    let scale = if unit.is_empty() {
+        bare_scale
+    } else {
+        units.iter().find(|(name, _)| name.eq_ignore_ascii_case(unit))?.1
+    };
+
+    match number.split_once('.') {
+        None => number.parse::<u64>().ok()?.checked_mul(scale),
+        Some((whole, fraction)) => {
+            if fraction.is_empty() || fraction.len() > 9 {
+                return None;
+            }
+            let whole = if whole.is_empty() { 0 } else { whole.parse::<u64>().ok()? };
+            let divisor = 10u64.pow(fraction.len() as u32);
+            let fraction = fraction.parse::<u64>().ok()?;
+            let scaled = fraction as u128 * scale as u128;
+            if !scaled.is_multiple_of(divisor as u128) {
+                return None;
+            }
+            let scaled_fraction = (scaled / divisor as u128) as u64;
+            whole.checked_mul(scale)?.checked_add(scaled_fraction)
+        }
+    }
+}
+
+fn format_scaled(value: u64, scale: u64) -> String {
+    let whole = value / scale;
+    let remainder = value % scale;
+    if remainder == 0 {
+        return whole.to_string();
+    }
+    let digits = scale.ilog10() as usize;
+    let fraction = format!("{:0width$}", remainder, width = digits);
+    format!("{}.{}", whole, fraction.trim_end_matches('0'))
+}
+
+macro_rules! impl_unit_serde {
+    ($type:ty, $from_integer:expr, $expecting:literal) => {
+        impl Serialize for $type {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $type {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                struct UnitVisitor;
+
+                impl Visitor<'_> for UnitVisitor {
+                    type Value = $type;
+
+                    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                        f.write_str($expecting)
+                    }
+
+                    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Self::Value, E> {
+                        $from_integer(value).map_err(E::custom)
+                    }
+
+                    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Self::Value, E> {
+                        let value = u64::try_from(value)
+                            .map_err(|_| E::invalid_value(de::Unexpected::Signed(value), &self))?;
+                        self.visit_u64(value)
+                    }
+
+                    fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+                        value.parse().map_err(E::custom)
+                    }
+                }
+
+                deserializer.deserialize_any(UnitVisitor)
+            }
+        }
+    };
+}
+
+impl_unit_serde!(LinkSpeed, |bps| Ok::<_, String>(LinkSpeed(bps)), "a speed such as \"100Mbps\" or an integer in bits per second");
+impl_unit_serde!(Interval, Interval::parse_secs, "a duration such as \"15s\" or an integer in seconds");
+
+impl JsonSchema for LinkSpeed {
+    fn schema_name() -> Cow<'static, str> {
+        "LinkSpeed".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "description": "Link speed with a unit (bps, Kbps, Mbps, Gbps) or an integer in bits per second",
+            "examples": ["100Mbps", "2.5Gbps", 100000000],
+            "oneOf": [
+                { "type": "integer", "minimum": 1 },
+                { "type": "string", "pattern": "^[0-9]*\\.?[0-9]+ *([KkMmGg]?b[Pp][Ss])?$" }
+            ]
+        })
+    }
+}
+
+impl JsonSchema for Interval {
+    fn schema_name() -> Cow<'static, str> {
+        "Interval".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "description": "Duration with a unit (ms, s, m, h) or an integer in seconds, at most a year",
+            "examples": ["15s", "2m", "1h", 15],
+            "oneOf": [
+                { "type": "integer", "minimum": 1, "maximum": MAX_INTERVAL_SECS },
+                { "type": "string", "pattern": "^[0-9]*\\.?[0-9]+ *([Mm][Ss]|[SsMmHh])?$" }
+            ]
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intervals_are_capped_at_a_year() {
+        assert_eq!("365d".parse::<Interval>().ok(), None);
+        assert_eq!("8760h".parse::<Interval>(), Ok(Interval::from_secs(MAX_INTERVAL_SECS)));
+        assert!("8761h".parse::<Interval>().unwrap_err().contains("too long"));
+        assert_eq!(serde_json::from_str::<Interval>("31536000").unwrap(), Interval::from_secs(MAX_INTERVAL_SECS));
+        assert!(serde_json::from_str::<Interval>("31536001").is_err());
+        assert!(serde_json::from_str::<Interval>("18446744073709551615").is_err());
+        assert!(serde_json::from_str::<Interval>("-1").is_err());
+    }
+
+    #[test]
+    fn speeds_round_trip() {
+        for (text, bps) in [("100Mbps", 100_000_000), ("2.5Gbps", 2_500_000_000), ("1.25Kbps", 1_250), ("10bps", 10), ("0bps", 0)] {
+            let speed: LinkSpeed = text.parse().unwrap();
+            assert_eq!(speed, LinkSpeed(bps));
+            assert_eq!(speed.to_string(), text);
+        }
+        assert_eq!("100 mbps".parse(), Ok(LinkSpeed(100_000_000)));
+        assert_eq!("1000".parse(), Ok(LinkSpeed(1_000)));
+    }
+
+    #[test]
+    fn rejects_bytes_and_sub_bit_speeds() {
+        assert!("100MBps".parse::<LinkSpeed>().unwrap_err().contains("not bytes"));
+        assert!("1GBPS".parse::<LinkSpeed>().unwrap_err().contains("not bytes"));
+        assert!("1.5bps".parse::<LinkSpeed>().is_err());
+        assert!("100MB".parse::<LinkSpeed>().is_err());
+    }
+
+    #[test]
+    fn intervals_round_trip() {
+        for (text, millis) in [("15s", 15_000), ("90s", 90_000), ("2m", 120_000), ("1h", 3_600_000), ("500ms", 500), ("0s", 0)] {
+            let interval: Interval = text.parse().unwrap();
+            assert_eq!(interval.duration(), Duration::from_millis(millis));
+            assert_eq!(interval.to_string(), text);
+        }
+        assert_eq!("1.5m".parse::<Interval>().unwrap().to_string(), "90s");
+        assert_eq!("30".parse(), Ok(Interval::from_secs(30)));
+    }
+
+    #[test]
+    fn rejects_sub_millisecond_intervals() {
+        assert!("0.0001s".parse::<Interval>().is_err());
+        assert!("1.0005s".parse::<Interval>().is_err());
+        assert_eq!("0.001s".parse::<Interval>().unwrap().to_string(), "1ms");
+    }
+}
@@ -0,0 +1,397 @@
+use hmac::{Hmac, Mac};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+use crate::config::AppConfig;
+use crate::history::unix_now;
+use crate::notify::{Notification, NotificationKind};
+
+const QUEUE_FILENAME: &str = "relink_webhooks.jsonl";
+const QUEUE_LOCK_FILENAME: &str = "relink_webhooks.lock";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+// Delays between attempts, doubling from the first
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_ATTEMPTS: u32 = 4;
+// Queued deliveries are retried this often and given up after QUEUE_MAX_AGE
+const QUEUE_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+const QUEUE_MAX_AGE: Duration = Duration::from_secs(3600);
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(30);
+const SIGNATURE_HEADER: &str = "X-Relink-Signature";
+
+/// An HTTP endpoint notifications are POSTed to as JSON
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebhookConfig {
+    /// http:// or https:// URL to POST to
+    pub url: String,
+    /// Extra request headers, e.g. for authentication
+    pub headers: BTreeMap<String, String>,
+    /// Key for an HMAC-SHA256 of the body, sent as "X-Relink-Signature: sha256=<hex>"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    /// Events to send, all of them if empty
+    pub events: Vec<NotificationKind>,
+}
+
+impl WebhookConfig {
+    pub fn has_valid_url(&self) -> bool {
+        self.url.starts_with("http://") || self.url.starts_with("https://")
+    }
+
+    fn wants(&self, event: NotificationKind) -> bool {
+        self.events.is_empty() || self.events.contains(&event)
+    }
+}
+
+// A delivery that failed all attempts, retried from disk for a while
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct QueuedDelivery {
+    url: String,
+    notification: Notification,
+    // Seconds since the Unix epoch
+    queued_at: u64,
+}
+
+enum Message {
+    Send(Box<Notification>),
+    Flush(Sender<()>),
+}
+
+static SENDER: OnceLock<Sender<Message>> = OnceLock::new();
+
+// Deliveries run on one background thread, in order, so a slow endpoint never delays a check
+fn sender() -> &'static Sender<Message> {
+    SENDER.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || run(receiver));
+        sender
+    })
+}
+
+// Starts the sender so deliveries queued on disk by an earlier run are retried
+pub fn start() {
+    if !AppConfig::global().webhooks.is_empty() || queue_path().exists() {
+        sender();
+    }
+}
+
+pub fn enqueue(notification: Notification) {
+    if AppConfig::global().webhooks.is_empty() {
+        return;
+    }
+    let _ = sender().send(Message::Send(Box::new(notification)));
+}
+
+// Waits for pending deliveries, for short-lived processes about to exit
+pub fn flush() {
+    let Some(sender) = SENDER.get() else { return };
+    let (done, wait) = mpsc::channel();
+    if sender.send(Message::Flush(done)).is_ok() && wait.recv_timeout(FLUSH_TIMEOUT).is_err() {
+        log::warn!("Webhook deliveries still pending after {:?}.", FLUSH_TIMEOUT);
+    }
+}
+
+fn run(receiver: Receiver<Message>) {
+    let agent = match build_agent() {
+        Ok(agent) => agent,
+        Err(e) => {
+            log::error!("Webhooks disabled, failed to set up TLS: {}", e);
+            return;
+        }
+    };
+    retry_queued(&agent);
+    loop {
+        match receiver.recv_timeout(QUEUE_RETRY_INTERVAL) {
+            Ok(Message::Send(notification)) => deliver_all(&agent, &notification),
+            Ok(Message::Flush(done)) => {
+                let _ = done.send(());
+            }
+            Err(RecvTimeoutError::Timeout) => retry_queued(&agent),
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+}
+
+fn build_agent() -> Result<ureq::Agent, native_tls::Error> {
+    let tls = native_tls::TlsConnector::new()?;
+    Ok(ureq::AgentBuilder::new()
+        .timeout(REQUEST_TIMEOUT)
+        .tls_connector(Arc::new(tls))
+        .build())
+}
+
+fn deliver_all(agent: &ureq::Agent, notification: &Notification) {
+    let config = AppConfig::global();
+    for webhook in config.webhooks.iter().filter(|webhook| webhook.wants(notification.event)) {
+        let mut delay = FIRST_RETRY_DELAY;
+        let mut attempt = 1;
+        let result = loop {
+            match post(agent, webhook, notification) {
+                Err(e) if e.retryable && attempt < MAX_ATTEMPTS => {
+                    log::debug!("Webhook {} attempt {} failed: {}", webhook.url, attempt, e.message);
+                    thread::sleep(delay);
+                    delay *= 2;
+                    attempt += 1;
+                }
+                result => break result,
+            }
+        };
+        match result {
+            Ok(()) => log::debug!("Webhook {} delivered {:?}.", webhook.url, notification.event),
+            Err(e) if e.retryable => {
+                log::warn!("Webhook {} failed after {} attempts, queued: {}", webhook.url, attempt, e.message);
+                let queued = QueuedDelivery {
+                    url: webhook.url.clone(),
+                    notification: notification.clone(),
+                    queued_at: unix_now(),
+                };
+                if let Err(e) = append_queued(&queued) {
+                    log::warn!("Failed to queue webhook delivery in {:?}: {}", queue_path(), e);
+                }
+            }
+            Err(e) => log::warn!("Webhook {} rejected {:?}: {}", webhook.url, notification.event, e.message),
+        }
+    }
+}
+
+struct DeliveryError {
+    message: String,
+    // Network errors, timeouts and server errors may go away, other rejections won't
+    retryable: bool,
+}
+
+fn post(agent: &ureq::Agent, webhook: &WebhookConfig, notification: &Notification) -> Result<(), DeliveryError> {
+    let body = serde_json::to_string(notification).expect("notifications serialize");
+    let mut request = agent.post(&webhook.url).set("Content-Type", "application/json");
+    for (name, value) in &webhook.headers {
+        request = request.set(name, value);
+    }
+    if let Some(secret) = &webhook.secret {
+        request = request.set(SIGNATURE_HEADER, &format!("sha256={}", sign(secret, &body)));
+    }
+
+    match request.send_string(&body) {
+        Ok(_) => Ok(()),
+        Err(ureq::Error::Status(code, _)) => Err(DeliveryError {
+            message: format!("HTTP {}", code),
+            retryable: code >= 500 || code == 408 || code == 429,
+        }),
+        Err(e) => Err(DeliveryError {
+            message: e.to_string(),
+            retryable: true,
+        }),
+    }
+}
+
+fn sign(secret: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body.as_bytes());
+    mac.finalize().into_bytes().iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{:02x}", byte);
+        hex
+    })
+}
+
+// Retries each queued delivery once. Drops those that are too old or whose webhook
+// is no longer configured. Holds the queue lock throughout, so another process's
+// sender neither delivers the same entries nor loses the ones it queues meanwhile.
+fn retry_queued(agent: &ureq::Agent) {
+    let _lock = match lock_queue() {
+        Ok(lock) => lock,
+        Err(e) => {
+            log::warn!("Failed to lock the webhook queue: {}", e);
+            return;
+        }
+    };
+    let queued = match load_queued() {
+        Ok(queued) if queued.is_empty() => return,
+        Ok(queued) => queued,
+        Err(e) => {
+            log::warn!("Failed to read queued webhook deliveries from {:?}: {}", queue_path(), e);
+            return;
+        }
+    };
+
+    let config = AppConfig::global();
+    let now = unix_now();
+    let mut remaining = Vec::new();
+    for delivery in queued {
+        if now.saturating_sub(delivery.queued_at) > QUEUE_MAX_AGE.as_secs() {
+            log::warn!("Dropped webhook delivery to {} queued for over {:?}.", delivery.url, QUEUE_MAX_AGE);
+            continue;
+        }
+        let Some(webhook) = config.webhooks.iter().find(|webhook| webhook.url == delivery.url) else {
+            continue;
+        };
+        match post(agent, webhook, &delivery.notification) {
+            Ok(()) => log::info!("Delivered queued webhook to {}.", delivery.url),
+            Err(e) if e.retryable => remaining.push(delivery),
+            Err(e) => log::warn!("Webhook {} rejected a queued delivery: {}", delivery.url, e.message),
+        }
+    }
+
+    if let Err(e) = save_queued(&remaining) {
+        log::warn!("Failed to update queued webhook deliveries in {:?}: {}", queue_path(), e);
+    }
+}
+
+// Next to the executable, like the restart history
+fn queue_path() -> PathBuf {
+    let mut path = env::current_exe().unwrap_or_default();
+    path.set_file_name(QUEUE_FILENAME);
+    path
+}
+
+// The service and a command-line restart may each run a sender, the queue file is
+// only read and rewritten while holding this. Released when the file is dropped.
+fn lock_queue() -> io::Result<File> {
+    let mut path = queue_path();
+    path.set_file_name(QUEUE_LOCK_FILENAME);
+    let file = File::options().create(true).truncate(false).write(true).open(path)?;
+    file.lock()?;
+    Ok(file)
+}
+
+fn load_queued() -> io::Result<Vec<QueuedDelivery>> {
+    let file = match File::open(queue_path()) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut queued = Vec::new();
+    for line in BufReader::new(file).lines() {
+        if let Ok(delivery) = serde_json::from_str(&line?) {
+            queued.push(delivery);
+        }
+    }
+    Ok(queued)
+}
+
+fn append_queued(delivery: &QueuedDelivery) -> io::Result<()> {
+    let _lock = lock_queue()?;
+    let mut queued = load_queued()?;
+    queued.push(delivery.clone());
+    save_queued(&queued)
+}
+
+fn save_queued(queued: &[QueuedDelivery]) -> io::Result<()> {
+    let path = queue_path();
+    if queued.is_empty() {
+        return match fs::remove_file(&path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        };
+    }
+    let temp_path = path.with_extension("jsonl.tmp");
+    let mut file = File::create(&temp_path)?;
+    for delivery in queued {
+        let line = serde_json::to_string(delivery).map_err(io::Error::other)?;
+        writeln!(file, "{}", line)?;
+    }
+    drop(file);
+    fs::rename(temp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    #[test]
+    fn signs_with_hmac_sha256() {
+        assert_eq!(
+            sign("key", "The quick brown fox jumps over the lazy dog"),
+            "f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+    }
+
+    // Answers one request with `code`, returning the URL and the request as received
+    fn serve_once(code: u16) -> (String, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request = String::new();
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+                request.push_str(&line);
+                if line.trim().is_empty() {
+                    break;
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            request.push_str(&String::from_utf8(body).unwrap());
+
+            let mut stream = stream;
+            write!(stream, "HTTP/1.1 {} Test\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", code).unwrap();
+            request
+        });
+        (url, server)
+    }
+
+    fn notification() -> Notification {
+        AppConfig::use_defaults();
+        Notification::new(NotificationKind::Degraded, "link degraded".to_string())
+    }
+
+    #[test]
+    fn delivers_signed_json() {
+        let (url, server) = serve_once(204);
+        let webhook = WebhookConfig {
+            url,
+            headers: BTreeMap::from([("Authorization".to_string(), "Bearer token".to_string())]),
+            secret: Some("key".to_string()),
+            events: Vec::new(),
+        };
+        let notification = notification();
+        assert!(post(&build_agent().unwrap(), &webhook, &notification).is_ok());
+
+        let request = server.join().unwrap();
+        let body = serde_json::to_string(&notification).unwrap();
+        let lowercase = request.to_lowercase();
+        assert!(request.starts_with("POST /hook HTTP/1.1\r\n"));
+        assert!(lowercase.contains("content-type: application/json\r\n"));
+        assert!(lowercase.contains("authorization: bearer token\r\n"));
+        assert!(lowercase.contains(&format!("x-relink-signature: sha256={}\r\n", sign("key", &body))));
+        assert!(request.ends_with(&format!("\r\n\r\n{}", body)));
+    }
+
+    #[test]
+    fn only_transient_failures_are_retryable() {
+        let agent = build_agent().unwrap();
+        for (code, retryable) in [(500, true), (503, true), (408, true), (429, true), (400, false), (401, false), (404, false)] {
+            let (url, server) = serve_once(code);
+            let webhook = WebhookConfig { url, ..WebhookConfig::default() };
+            let error = post(&agent, &webhook, &notification()).err().unwrap();
+            server.join().unwrap();
+            assert_eq!(error.retryable, retryable, "HTTP {}", code);
+            assert_eq!(error.message, format!("HTTP {}", code));
+        }
+
+        // Nothing listens on a port that was just released
+        let address = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let webhook = WebhookConfig { url: format!("http://{}/hook", address), ..WebhookConfig::default() };
+        assert!(post(&agent, &webhook, &notification()).err().unwrap().retryable);
+    }
+}
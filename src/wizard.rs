@@ -0,0 +1,185 @@
+use clap::Args;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+use crate::config::AppConfig;
+use crate::device::{list_adapters, AdapterInfo};
+use crate::units::LinkSpeed;
+
+// Common Ethernet and Wi-Fi rates, ascending. The proposed threshold is the
+// fastest of these below the adapter's maximum, so any fallback counts as degraded.
+const STANDARD_SPEEDS: [u64; 10] = [
+    10_000_000,
+    100_000_000,
+    1_000_000_000,
+    2_500_000_000,
+    5_000_000_000,
+    10_000_000_000,
+    25_000_000_000,
+    40_000_000_000,
+    50_000_000_000,
+    100_000_000_000,
+];
+
+#[derive(Args, Debug)]
+pub struct InitOptions {
+    /// Take everything from flags instead of prompting
+    #[arg(long, requires = "adapter")]
+    non_interactive: bool,
+    /// Adapter name or part of it
+    #[arg(long)]
+    adapter: Option<String>,
+    /// Link speed threshold, proposed from the adapter's maximum speed if omitted
+    #[arg(long)]
+    threshold: Option<LinkSpeed>,
+    /// Where to write the config, defaults to the active config path
+    #[arg(long, value_name = "PATH")]
+    output: Option<PathBuf>,
+    /// Overwrite an existing file without asking
+    #[arg(long)]
+    force: bool,
+}
+
+// `relink config init`: picks the adapter and threshold, then writes a validated config
+pub fn run_init(options: &InitOptions) -> i32 {
+    match init_config(options) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+fn init_config(options: &InitOptions) -> Result<(), String> {
+    let output = options.output.clone().unwrap_or_else(AppConfig::get_path);
+    // Keep the other settings of an existing config, only the adapter and threshold change
+    let mut config = if output.exists() {
+        AppConfig::load_from(&output).unwrap_or_else(|e| {
+            eprintln!("Warning: {}", e);
+            eprintln!("Starting from the default config instead.");
+            AppConfig::default()
+        })
+    } else {
+        AppConfig::default()
+    };
+
+    let adapters: Vec<AdapterInfo> = list_adapters()
+        .map_err(|e| format!("Failed to list network adapters: {:?}", e))?
+        .into_iter()
+        .filter(AdapterInfo::is_physical)
+        .collect();
+    if adapters.is_empty() {
+        return Err("No network adapters found.".to_string());
+    }
+
+    let adapter = match &options.adapter {
+        Some(name) => adapters
+            .iter()
+            .find(|adapter| adapter.matches(name))
+            .ok_or_else(|| format!("No adapter matches '{}'.", name))?,
+        None => choose_adapter(&adapters, &config.target_adapter_name)?,
+    };
+
+    let proposed = propose_threshold(adapter);
+    let threshold = match options.threshold {
+        Some(threshold) => threshold,
+        None if options.non_interactive => proposed,
+        None => {
+            let answer = prompt(&format!("Link speed threshold [{}]: ", proposed))?;
+            if answer.is_empty() { proposed } else { answer.parse()? }
+        }
+    };
+
+    // SetupAPI matches the device description exactly, so use it rather than the interface alias
+    config.target_adapter_name = adapter.description.clone();
+    config.link_speed_threshold_bps = threshold;
+
+    let mut problems = config.validate();
+    if let Some(max_speed) = adapter.max_speed {
+        if threshold >= max_speed {
+            problems.push(format!(
+                "link_speed_threshold_bps ({}) is not below the adapter's maximum speed ({}), a healthy link would count as degraded",
+                threshold, max_speed
+            ));
+        }
+    }
+    if !problems.is_empty() {
+        return Err(format!("Not writing an invalid config:\n  - {}", problems.join("\n  - ")));
+    }
+
+    if output.exists() && !options.force {
+        if options.non_interactive {
+            return Err(format!("{} already exists, pass --force to overwrite it.", output.display()));
+        }
+        let answer = prompt(&format!("Overwrite {}? [y/N]: ", output.display()))?;
+        if !answer.eq_ignore_ascii_case("y") && !answer.eq_ignore_ascii_case("yes") {
+            return Err("Aborted, nothing written.".to_string());
+        }
+    }
+
+    config.save_to(&output).map_err(|e| e.to_string())?;
+    println!(
+        "Wrote {} for '{}' with threshold {}.",
+        output.display(),
+        config.target_adapter_name,
+        threshold
+    );
+    Ok(())
+}
+
+fn choose_adapter<'a>(adapters: &'a [AdapterInfo], current: &str) -> Result<&'a AdapterInfo, String> {
+    println!("Detected network adapters:");
+    println!("  #  {:<28} {:<48} {:>10} {:>10}", "Name", "Description", "Speed", "Max");
+    for (index, adapter) in adapters.iter().enumerate() {
+        let max_speed = adapter.max_speed.map(|speed| speed.to_string()).unwrap_or_else(|| "?".to_string());
+        println!(
+            "{:>3}  {:<28} {:<48} {:>10} {:>10}",
+            index + 1,
+            adapter.friendly_name,
+            adapter.description,
+            adapter.receive_speed.to_string(),
+            max_speed
+        );
+    }
+
+    let default = adapters.iter().position(|adapter| adapter.description == current);
+    let question = match default {
+        Some(index) => format!("Adapter to monitor [{}]: ", index + 1),
+        None => "Adapter to monitor: ".to_string(),
+    };
+
+    loop {
+        let answer = prompt(&question)?;
+        let choice = match (answer.is_empty(), default) {
+            (true, Some(index)) => Some(index),
+            _ => answer.parse::<usize>().ok().and_then(|n| n.checked_sub(1)),
+        };
+        match choice.and_then(|index| adapters.get(index)) {
+            Some(adapter) => return Ok(adapter),
+            None => println!("Enter a number between 1 and {}.", adapters.len()),
+        }
+    }
+}
+
+// Falls back to the current speed when the driver doesn't report a maximum
+fn propose_threshold(adapter: &AdapterInfo) -> LinkSpeed {
+    let reference = adapter.max_speed.unwrap_or(adapter.receive_speed).bps();
+    STANDARD_SPEEDS
+        .iter()
+        .rev()
+        .find(|&&speed| speed < reference)
+        .map(|&speed| LinkSpeed(speed))
+        .unwrap_or(LinkSpeed((reference / 2).max(1)))
+}
+
+fn prompt(question: &str) -> Result<String, String> {
+    print!("{}", question);
+    io::stdout().flush().map_err(|e| e.to_string())?;
+    let mut answer = String::new();
+    let read = io::stdin().lock().read_line(&mut answer).map_err(|e| e.to_string())?;
+    if read == 0 {
+        return Err("No input, aborted.".to_string());
+    }
+    Ok(answer.trim().to_string())
+}
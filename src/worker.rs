@@ -0,0 +1,439 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::breaker::CircuitBreaker;
+use crate::config::AppConfig;
+use crate::device::{check_and_fix_network, CheckOutcome, CheckReport};
+use crate::history::{unix_now, RestartRecord};
+use crate::metrics;
+use crate::notify::{self, Notification};
+
+// Windows usually sends ResumeAutomatic and ResumeSuspend for the same wake,
+// sometimes several seconds apart.
+const WAKE_COALESCE_WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Trigger {
+    Wake,
+    Periodic,
+    Manual,
+    LinkChange,
+}
+
+impl Trigger {
+    pub fn name(self) -> &'static str {
+        match self {
+            Trigger::Wake => "wake",
+            Trigger::Periodic => "periodic",
+            Trigger::Manual => "manual",
+            Trigger::LinkChange => "link change",
+        }
+    }
+
+    pub fn is_forced(self) -> bool {
+        matches!(self, Trigger::Wake | Trigger::Manual)
+    }
+
+    // Higher wins when several triggers are merged into one job
+    fn priority(self) -> u8 {
+        match self {
+            Trigger::Periodic | Trigger::LinkChange => 0,
+            Trigger::Manual => 1,
+            Trigger::Wake => 2,
+        }
+    }
+}
+
+// Whether a requested check may restart a degraded adapter. Ordered, so merged requests
+// get the most permissive policy among them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FixPolicy {
+    Never,
+    // Unless automatic actions are paused
+    UnlessPaused,
+    // Even while paused, like the SCM check control
+    Always,
+}
+
+impl FixPolicy {
+    fn allows(self, paused: bool) -> bool {
+        match self {
+            FixPolicy::Never => false,
+            FixPolicy::UnlessPaused => !paused,
+            FixPolicy::Always => true,
+        }
+    }
+}
+
+// A requested check, as queued
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Job {
+    pub trigger: Trigger,
+    pub fix: FixPolicy,
+}
+
+impl Job {
+    pub fn new(trigger: Trigger, fix: FixPolicy) -> Self {
+        Self { trigger, fix }
+    }
+
+    // The strongest trigger and the most permissive policy. A request without fixing that
+    // is merged into a wake check may so get a report of a restart the wake asked for.
+    fn merge(self, other: Job) -> Job {
+        Job {
+            trigger: if other.trigger.priority() > self.trigger.priority() { other.trigger } else { self.trigger },
+            fix: self.fix.max(other.fix),
+        }
+    }
+}
+
+enum Message {
+    // The report is sent back once a check covering this job has run
+    Run(Job, Option<Sender<CheckReport>>),
+    ResetBreaker,
+    Stop,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LastCheck {
+    // Seconds since the Unix epoch
+    pub time: u64,
+    pub trigger: Trigger,
+    pub report: CheckReport,
+}
+
+// Where the monitored adapter is in the check cycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AdapterState {
+    // No check has run yet
+    #[default]
+    Starting,
+    // Waiting for the adapter to initialize after a wake
+    Settling,
+    // Checking, and restarting if needed
+    Checking,
+    Healthy,
+    Degraded,
+    NotFound,
+    Error,
+}
+
+impl AdapterState {
+    fn after(outcome: &CheckOutcome) -> Self {
+        match outcome {
+            CheckOutcome::Healthy | CheckOutcome::Fixed => AdapterState::Healthy,
+            CheckOutcome::NotFixed { .. } => AdapterState::Degraded,
+            CheckOutcome::AdapterNotFound => AdapterState::NotFound,
+            CheckOutcome::Error { .. } => AdapterState::Error,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AdapterState::Starting => "starting",
+            AdapterState::Settling => "settling after wake",
+            AdapterState::Checking => "checking",
+            AdapterState::Healthy => "healthy",
+            AdapterState::Degraded => "degraded",
+            AdapterState::NotFound => "not found",
+            AdapterState::Error => "error",
+        }
+    }
+}
+
+// What the worker is doing and last did, shared with status queries
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WorkerStatus {
+    pub state: AdapterState,
+    pub last_check: Option<LastCheck>,
+    // Seconds since the Unix epoch
+    pub last_wake: Option<u64>,
+    pub breaker_remaining: u32,
+    // Of the monitored adapter, by a check or a manual restart in this process
+    pub last_restart: Option<RestartRecord>,
+}
+
+#[derive(Clone)]
+pub struct RecoveryQueue {
+    sender: Sender<Message>,
+    paused: Arc<AtomicBool>,
+    status: Arc<Mutex<WorkerStatus>>,
+    // Copy of the worker's breaker as of its last change, so restarts age out of it
+    // between checks without waiting for the worker
+    breaker: Arc<Mutex<CircuitBreaker>>,
+}
+
+impl RecoveryQueue {
+    // Manual checks may fix while paused, others only while not
+    pub fn submit(&self, trigger: Trigger) {
+        let fix = if trigger == Trigger::Manual { FixPolicy::Always } else { FixPolicy::UnlessPaused };
+        if self.sender.send(Message::Run(Job::new(trigger, fix), None)).is_err() {
+            log::warn!("Recovery worker is not running, dropping {:?} trigger.", trigger);
+        }
+    }
+
+    // Like submit, but returns a receiver for the report of the check that handles it.
+    // The receiver disconnects if the worker stops first.
+    pub fn request(&self, job: Job) -> Receiver<CheckReport> {
+        let (reply, report) = mpsc::channel();
+        let _ = self.sender.send(Message::Run(job, Some(reply)));
+        report
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    pub fn status(&self) -> WorkerStatus {
+        let mut status = self.status.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        let mut breaker = self.breaker.lock().unwrap_or_else(|e| e.into_inner());
+        let config = AppConfig::global();
+        breaker.set_limits(config.breaker_max_restarts, config.breaker_window_secs.duration());
+        status.breaker_remaining = breaker.remaining();
+        status
+    }
+
+    // While paused checks keep running, but only manual checks may restart the adapter.
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::SeqCst);
+    }
+
+    pub fn set_last_restart(&self, record: RestartRecord) {
+        self.status.lock().unwrap_or_else(|e| e.into_inner()).last_restart = Some(record);
+    }
+
+    pub fn reset_breaker(&self) {
+        let _ = self.sender.send(Message::ResetBreaker);
+    }
+
+    // Lets the job in progress finish, then ends the worker. Queued triggers are discarded.
+    pub fn stop(&self) {
+        let _ = self.sender.send(Message::Stop);
+    }
+}
+
+struct Worker {
+    receiver: Receiver<Message>,
+    paused: Arc<AtomicBool>,
+    status: Arc<Mutex<WorkerStatus>>,
+    breaker: CircuitBreaker,
+    shared_breaker: Arc<Mutex<CircuitBreaker>>,
+    // What the last check found. The shared status also passes through Settling and Checking.
+    state: AdapterState,
+    last_wake: Option<Instant>,
+    // Callers waiting for the next check to finish
+    waiting: Vec<Sender<CheckReport>>,
+}
+
+// All checks run on this single thread, so at most one recovery is ever in flight.
+pub fn spawn_worker() -> (RecoveryQueue, JoinHandle<()>) {
+    let (sender, receiver) = mpsc::channel();
+    let paused = Arc::new(AtomicBool::new(false));
+    let config = AppConfig::global();
+    let status = Arc::new(Mutex::new(WorkerStatus {
+        breaker_remaining: config.breaker_max_restarts,
+        ..WorkerStatus::default()
+    }));
+    let breaker = CircuitBreaker::new(config.breaker_max_restarts, config.breaker_window_secs.duration());
+    let shared_breaker = Arc::new(Mutex::new(breaker.clone()));
+    let mut worker = Worker {
+        receiver,
+        paused: paused.clone(),
+        status: status.clone(),
+        breaker,
+        shared_breaker: shared_breaker.clone(),
+        state: AdapterState::Starting,
+        last_wake: None,
+        waiting: Vec::new(),
+    };
+    let handle = thread::spawn(move || worker.run());
+    (RecoveryQueue { sender, paused, status, breaker: shared_breaker }, handle)
+}
+
+impl Worker {
+    fn run(&mut self) {
+        loop {
+            let job = match self.receiver.recv() {
+                Ok(Message::Run(job, reply)) => {
+                    self.waiting.extend(reply);
+                    job
+                }
+                Ok(Message::ResetBreaker) => {
+                    self.reset_breaker();
+                    continue;
+                }
+                Ok(Message::Stop) | Err(_) => break,
+            };
+            let Some(Job { mut trigger, mut fix }) = self.merge_pending(job) else {
+                break;
+            };
+
+            if trigger == Trigger::Wake && self.last_wake.is_some_and(|at| at.elapsed() < WAKE_COALESCE_WINDOW) {
+                log::info!("Duplicate wake event within {:?}, already handled.", WAKE_COALESCE_WINDOW);
+                if self.waiting.is_empty() {
+                    continue;
+                }
+                // Someone asked for a check that got merged into the duplicate, run it
+                trigger = Trigger::Manual;
+            } else if trigger == Trigger::Wake {
+                self.last_wake = Some(Instant::now());
+                self.update_status(|status| {
+                    status.state = AdapterState::Settling;
+                    status.last_wake = Some(unix_now());
+                });
+
+                let wait_time = AppConfig::global().wait_after_wake_secs.duration();
+                log::info!("Waiting {:?} for network adapter initialization...", wait_time);
+                if !self.settle(wait_time, &mut fix) {
+                    break;
+                }
+            }
+
+            let config = AppConfig::global();
+            self.breaker.set_limits(config.breaker_max_restarts, config.breaker_window_secs.duration());
+
+            let fix_allowed = fix.allows(self.paused.load(Ordering::SeqCst));
+            let remaining_before = self.breaker.remaining();
+            self.update_status(|status| status.state = AdapterState::Checking);
+            let report = check_and_fix_network(trigger, fix_allowed, &mut self.breaker);
+
+            let state = AdapterState::after(&report.outcome);
+            metrics::record_check(trigger, &report.outcome, self.state);
+            if trigger == Trigger::Wake && state == AdapterState::Healthy {
+                if let Some(woke_at) = self.last_wake {
+                    metrics::record_wake_to_healthy(woke_at.elapsed());
+                }
+            }
+
+            for reply in self.waiting.drain(..) {
+                let _ = reply.send(report.clone());
+            }
+            let breaker_remaining = self.breaker.remaining();
+            self.notify(trigger, &report, remaining_before, breaker_remaining);
+            self.state = state;
+            self.share_breaker();
+            self.update_status(|status| {
+                status.state = state;
+                status.breaker_remaining = breaker_remaining;
+                if let Some(record) = &report.restart {
+                    status.last_restart = Some(record.clone());
+                }
+                status.last_check = Some(LastCheck { time: unix_now(), trigger, report });
+            });
+        }
+
+        log::info!("Recovery worker stopped.");
+    }
+
+    fn reset_breaker(&mut self) {
+        self.breaker.reset();
+        let breaker_remaining = self.breaker.remaining();
+        self.share_breaker();
+        self.update_status(|status| status.breaker_remaining = breaker_remaining);
+        log::info!("Circuit breaker reset.");
+    }
+
+    // Compares against the state before this check, so a link that stays degraded or
+    // a breaker that stays open is only reported once
+    fn notify(&self, trigger: Trigger, report: &CheckReport, remaining_before: u32, breaker_remaining: u32) {
+        if let Some(speed) = report.speed.filter(|_| report.is_degraded()) {
+            if self.state != AdapterState::Degraded || report.restart.is_some() {
+                notify::publish(Notification::degraded(speed, trigger));
+            }
+        }
+        if let Some(record) = &report.restart {
+            notify::publish(Notification::restart(record));
+        }
+        if breaker_remaining == 0 && remaining_before > 0 {
+            notify::publish(Notification::breaker_opened());
+        }
+    }
+
+    fn share_breaker(&self) {
+        *self.shared_breaker.lock().unwrap_or_else(|e| e.into_inner()) = self.breaker.clone();
+    }
+
+    fn update_status(&self, update: impl FnOnce(&mut WorkerStatus)) {
+        update(&mut self.status.lock().unwrap_or_else(|e| e.into_inner()));
+    }
+
+    // Folds everything already queued into one job. Returns None if a stop request was queued.
+    fn merge_pending(&mut self, first: Job) -> Option<Job> {
+        let mut merged = first;
+        while let Ok(next) = self.receiver.try_recv() {
+            match next {
+                Message::Run(job, reply) => {
+                    self.waiting.extend(reply);
+                    merged = merged.merge(job);
+                }
+                Message::ResetBreaker => self.reset_breaker(),
+                Message::Stop => return None,
+            }
+        }
+        Some(merged)
+    }
+
+    // Sleeps through the post-wake delay, swallowing jobs that arrive meanwhile into `fix`.
+    // Returns false if the worker was asked to stop meanwhile.
+    fn settle(&mut self, wait_time: Duration, fix: &mut FixPolicy) -> bool {
+        let deadline = Instant::now() + wait_time;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.receiver.recv_timeout(remaining) {
+                Ok(Message::Run(job, reply)) => {
+                    // The check after the delay answers these too
+                    self.waiting.extend(reply);
+                    *fix = (*fix).max(job.fix);
+                    if job.trigger == Trigger::Wake {
+                        log::info!("Coalesced duplicate wake event.");
+                    }
+                }
+                Ok(Message::ResetBreaker) => self.reset_breaker(),
+                Ok(Message::Stop) | Err(RecvTimeoutError::Disconnected) => return false,
+                Err(RecvTimeoutError::Timeout) => return true,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merged_jobs_keep_the_strongest_trigger_and_most_permissive_policy() {
+        let no_fix = Job::new(Trigger::Manual, FixPolicy::Never);
+        let wake = Job::new(Trigger::Wake, FixPolicy::UnlessPaused);
+        assert_eq!(no_fix.merge(wake), Job::new(Trigger::Wake, FixPolicy::UnlessPaused));
+        assert_eq!(wake.merge(Job::new(Trigger::Periodic, FixPolicy::Always)), Job::new(Trigger::Wake, FixPolicy::Always));
+        assert_eq!(no_fix.merge(Job::new(Trigger::Periodic, FixPolicy::Never)), no_fix);
+    }
+
+    #[test]
+    fn link_change_checks_keep_their_trigger() {
+        AppConfig::use_defaults();
+        let (queue, worker) = spawn_worker();
+        queue.submit(Trigger::LinkChange);
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while queue.status().last_check.is_none() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(20));
+        }
+        queue.stop();
+        worker.join().unwrap();
+        assert_eq!(queue.status().last_check.unwrap().trigger, Trigger::LinkChange);
+    }
+
+    #[test]
+    fn fix_policies_respect_pause() {
+        assert!(!FixPolicy::Never.allows(false));
+        assert!(FixPolicy::UnlessPaused.allows(false));
+        assert!(!FixPolicy::UnlessPaused.allows(true));
+        assert!(FixPolicy::Always.allows(true));
+    }
+}